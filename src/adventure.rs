@@ -0,0 +1,263 @@
+use crate::ascii::{Ascii, Output};
+use crate::intcode::{Computer, Snapshot, Step};
+use crate::patch::Patch;
+use std::fs::{read_to_string, write, File};
+use std::io::{stdin, stdout, BufWriter, Write};
+use std::iter::from_fn;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(parse(from_os_str))]
+    program: PathBuf,
+    /// Records every line entered to a transcript.
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Plays the lines of a transcript instead of reading from the terminal.
+    #[structopt(long, parse(from_os_str))]
+    replay: Option<PathBuf>,
+}
+
+/// A game in progress.
+struct Session {
+    ascii: Ascii,
+    /// The prompt the program printed before waiting for input.
+    prompt: String,
+    /// The state before each command, and its prompt, to undo them.
+    history: Vec<(Ascii, String)>,
+    terminated: bool,
+}
+
+/// Precedes the snapshot in a checkpoint, as snapshots do not include partial lines.
+const PROMPT: &str = "prompt ";
+
+const HELP: &str = "\
+/undo              undo the last command, or the last load
+/save <file>       save a checkpoint
+/load <file>       restore a checkpoint
+/help              show this help
+/quit              exit
+
+Anything else is sent to the program.";
+
+// Playing
+
+impl Session {
+    fn new(ascii: Ascii) -> Session {
+        Session {
+            ascii,
+            prompt: String::new(),
+            history: Vec::new(),
+            terminated: false,
+        }
+    }
+
+    /// Runs the program until it waits for input, printing what it prints.
+    fn advance(&mut self) -> Result<(), failure::Error> {
+        let (outputs, step) = self.ascii.run();
+
+        for output in outputs {
+            match output {
+                Output::Line(line) => println!("{}", line),
+                Output::Value(value) => println!("{}", value),
+            }
+        }
+
+        self.prompt = self.ascii.take_partial();
+        self.terminated = matches!(step?, Step::Terminated);
+
+        if self.terminated {
+            println!("[the program has terminated, /undo or /load to continue]");
+        }
+
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) {
+        self.history.push((self.ascii.clone(), self.prompt.clone()));
+    }
+
+    fn save(&self, path: &str) -> Result<(), failure::Error> {
+        let text = format!("{}{}\n{}", PROMPT, self.prompt, self.ascii.snapshot());
+        Ok(write(path, text)?)
+    }
+
+    /// Restores a checkpoint, which may also be a bare snapshot without a prompt.
+    fn load(&mut self, path: &str) -> Result<(), failure::Error> {
+        let text = read_to_string(path)?;
+        let (prompt, snapshot) = match text.strip_prefix(PROMPT) {
+            Some(rest) => rest.split_at(rest.find('\n').unwrap_or(rest.len())),
+            None => ("", &text[..]),
+        };
+        let snapshot: Snapshot = snapshot.trim_start_matches('\n').parse()?;
+
+        self.checkpoint();
+        self.ascii = Ascii::restore(snapshot);
+        self.prompt = prompt.to_string();
+        self.terminated = false;
+
+        Ok(())
+    }
+
+    /// Handles a line of input, returns false to exit.
+    fn command(&mut self, line: &str) -> Result<bool, failure::Error> {
+        let args: Vec<_> = line.split_whitespace().collect();
+
+        match args.as_slice() {
+            ["/undo"] => {
+                let (ascii, prompt) = self
+                    .history
+                    .pop()
+                    .ok_or_else(|| failure::err_msg("nothing to undo"))?;
+                self.ascii = ascii;
+                self.prompt = prompt;
+                self.terminated = false;
+                println!("undone");
+            }
+            ["/save", file] => {
+                self.save(file)?;
+                println!("saved to {}", file);
+            }
+            ["/load", file] => {
+                self.load(file)?;
+                println!("loaded {}", file);
+            }
+            ["/help"] => println!("{}", HELP),
+            ["/quit"] => return Ok(false),
+            [command, ..] if command.starts_with('/') => {
+                return Err(failure::err_msg(format!(
+                    "unknown command `{}`, try /help",
+                    command
+                )))
+            }
+            _ if self.terminated => {
+                return Err(failure::err_msg("the program has terminated"));
+            }
+            _ => {
+                self.checkpoint();
+                self.ascii.send(line)?;
+                self.advance()?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+// Main
+
+fn read_line() -> Result<Option<String>, failure::Error> {
+    let mut line = String::new();
+    if stdin().read_line(&mut line)? == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let program = read_to_string(&options.program)?;
+    let mut session = Session::new(Ascii::new(Computer::new(
+        &options.patch.apply(Computer::parse(&program)?)?,
+    )));
+
+    let mut transcript = match &options.record {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let replay = options.replay.as_ref().map(read_to_string).transpose()?;
+    let mut replayed = replay.as_ref().map(|text| text.lines());
+
+    let mut lines = from_fn(|| match replayed.as_mut() {
+        Some(lines) => lines.next().map(|line| Ok(line.to_string())),
+        None => read_line().transpose(),
+    });
+
+    session.advance()?;
+
+    loop {
+        print!("{}", session.prompt);
+        stdout().flush()?;
+
+        let line = match lines.next().transpose()? {
+            Some(line) => line,
+            None => break,
+        };
+
+        // the terminal echoes what is typed, but a replayed line must be printed
+        if options.replay.is_some() {
+            println!("{}", line);
+        }
+
+        if let Some(transcript) = transcript.as_mut() {
+            writeln!(transcript, "{}", line)?;
+            transcript.flush()?;
+        }
+
+        match session.command(&line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => println!("error: {}", err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::intcode::Dialect;
+    use std::env::temp_dir;
+    use std::fs::remove_file;
+
+    /// Prompts with the number of lines read so far plus one, as `1>`.
+    const COUNTER: &str = "
+        loop: add [n], #1, [n]
+              add [n], #48, [c]
+              out [c]
+              out #62
+        wait: in [x]
+              eq [x], #10, [t]
+              jz [t], #wait
+              jz #0, #loop
+        n:    data 0
+        c:    data 0
+        x:    data 0
+        t:    data 0
+    ";
+
+    #[test]
+    fn save_and_load() {
+        let program = assemble(COUNTER, &Dialect::FULL).unwrap();
+        let mut session = Session::new(Ascii::new(Computer::new(&program)));
+        session.advance().unwrap();
+        assert_eq!(session.prompt, "1>");
+
+        let path = temp_dir().join(format!("adventure-{}.save", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        session.command("look").unwrap();
+        assert_eq!(session.prompt, "2>");
+        session.command(&format!("/save {}", path)).unwrap();
+        session.command("north").unwrap();
+        assert_eq!(session.prompt, "3>");
+
+        session.command(&format!("/load {}", path)).unwrap();
+        remove_file(path).unwrap();
+        assert_eq!(session.prompt, "2>");
+
+        session.command("south").unwrap();
+        assert_eq!(session.prompt, "3>");
+
+        session.command("/undo").unwrap();
+        session.command("/undo").unwrap();
+        assert_eq!(session.prompt, "3>");
+    }
+}
@@ -0,0 +1,478 @@
+use crate::intcode::{Computer, Dialect, Disassembled, Instruction, Mode, Opcode, Operand};
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{stdin, Read};
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+    /// Prints the control-flow graph as Graphviz DOT.
+    #[structopt(long)]
+    dot: bool,
+}
+
+/// A run of reachable instructions that is only entered at its start.
+pub struct Block {
+    pub start: usize,
+    /// The addresses of the instructions in the block.
+    pub instructions: Vec<usize>,
+    /// The blocks that execution may continue at.
+    pub successors: Vec<usize>,
+    /// Whether the block ends with a jump to a target read from memory.
+    pub indirect: bool,
+}
+
+/// Whether a write may change the program's code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Writes to a fixed address that holds a reachable instruction.
+    Code,
+    /// Writes to an address relative to the relative base, which is not known statically.
+    Unknown,
+}
+
+/// An instruction that writes to memory in a way that may overwrite code.
+pub struct Write {
+    pub address: usize,
+    pub dest: Operand,
+    pub overwrite: Overwrite,
+}
+
+pub struct Analysis {
+    /// The reachable instructions, by address.
+    pub instructions: BTreeMap<usize, Disassembled>,
+    pub blocks: Vec<Block>,
+    /// Reachable addresses that do not hold a valid instruction, or are outside the program.
+    pub invalid: BTreeSet<usize>,
+    pub writes: Vec<Write>,
+}
+
+// Analysis
+
+/// The addresses execution may continue at after an instruction, and whether it may also jump to
+/// an address that is not known statically.
+fn successors(
+    address: usize,
+    instruction: &Instruction,
+    operands: &[Operand],
+) -> (Vec<usize>, bool) {
+    let next = address + operands.len() + 1;
+
+    if instruction.is(Opcode::Hlt) {
+        (Vec::new(), false)
+    } else if instruction.is(Opcode::Jnz) || instruction.is(Opcode::Jz) {
+        let (condition, target) = (operands[0], operands[1]);
+        let jumps = |value: i64| (value != 0) == instruction.is(Opcode::Jnz);
+
+        let (may_jump, may_continue) = match condition.mode {
+            Mode::Immediate => (jumps(condition.value), !jumps(condition.value)),
+            _ => (true, true),
+        };
+
+        let mut successors = Vec::new();
+        if may_continue {
+            successors.push(next);
+        }
+
+        match target.mode {
+            Mode::Immediate if may_jump => {
+                // a jump to a negative address always fails, so it has no successor
+                if target.value >= 0 {
+                    successors.push(target.value as usize);
+                }
+                (successors, false)
+            }
+            _ => (successors, may_jump),
+        }
+    } else {
+        (vec![next], false)
+    }
+}
+
+pub fn analyze(opcodes: &[i64], dialect: &Dialect) -> Analysis {
+    let mut instructions = BTreeMap::new();
+    let mut invalid = BTreeSet::new();
+    let mut edges = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut pending = vec![0];
+
+    leaders.insert(0);
+
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) || invalid.contains(&address) {
+            continue;
+        }
+
+        if address >= opcodes.len() {
+            invalid.insert(address);
+            continue;
+        }
+
+        let line = Disassembled::decode(opcodes, address, dialect);
+        let (next, indirect, ends) = match &line {
+            Disassembled::Instruction {
+                instruction,
+                operands,
+            } => {
+                let (next, indirect) = successors(address, instruction, operands);
+                let ends = [Opcode::Jnz, Opcode::Jz, Opcode::Hlt]
+                    .iter()
+                    .any(|opcode| instruction.is(*opcode));
+                (next, indirect, ends)
+            }
+            Disassembled::Data(_) => {
+                invalid.insert(address);
+                continue;
+            }
+        };
+
+        pending.extend(next.iter().copied());
+        edges.insert(address, (next, indirect, ends));
+        instructions.insert(address, line);
+    }
+
+    let code: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(address, line)| *address..*address + line.len())
+        .collect();
+
+    // blocks start at jump targets, and wherever execution falls through to an instruction that
+    // is not the next one by address, as instructions can overlap
+    let addresses: Vec<usize> = edges.keys().copied().collect();
+    for (i, (next, _, ends)) in edges.values().enumerate() {
+        let following = addresses.get(i + 1).copied();
+        if *ends || next.first().copied() != following {
+            leaders.extend(next.iter().copied());
+        }
+    }
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for (address, (next, indirect, _)) in edges.iter() {
+        match blocks.last_mut() {
+            Some(block)
+                if !leaders.contains(address)
+                    && !block.indirect
+                    && block.successors == [*address] =>
+            {
+                block.instructions.push(*address);
+                block.successors = next.clone();
+                block.indirect = *indirect;
+            }
+            _ => blocks.push(Block {
+                start: *address,
+                instructions: vec![*address],
+                successors: next.clone(),
+                indirect: *indirect,
+            }),
+        }
+    }
+
+    let writes = instructions
+        .iter()
+        .filter_map(|(address, line)| match line {
+            Disassembled::Instruction {
+                instruction,
+                operands,
+            } => {
+                let dest = operands[instruction.dest? - 1];
+                let overwrite = match dest.mode {
+                    Mode::Position if code.contains(&(dest.value as usize)) => Overwrite::Code,
+                    Mode::Relative => Overwrite::Unknown,
+                    _ => return None,
+                };
+
+                Some(Write {
+                    address: *address,
+                    dest,
+                    overwrite,
+                })
+            }
+            Disassembled::Data(_) => None,
+        })
+        .collect();
+
+    Analysis {
+        instructions,
+        blocks,
+        invalid,
+        writes,
+    }
+}
+
+impl Analysis {
+    /// Whether the program may overwrite its own code, `None` if that cannot be determined
+    /// statically because of relative writes or indirect jumps.
+    pub fn self_modifying(&self) -> Option<bool> {
+        if self
+            .writes
+            .iter()
+            .any(|write| write.overwrite == Overwrite::Code)
+        {
+            Some(true)
+        } else if !self.writes.is_empty() || self.blocks.iter().any(|block| block.indirect) {
+            None
+        } else {
+            Some(false)
+        }
+    }
+}
+
+// Formatting
+
+fn print_text(analysis: &Analysis) {
+    println!(
+        "{} reachable instructions in {} blocks",
+        analysis.instructions.len(),
+        analysis.blocks.len()
+    );
+
+    for block in analysis.blocks.iter() {
+        println!();
+        println!(
+            "block {} -> {}{}",
+            block.start,
+            if block.successors.is_empty() {
+                "-".to_string()
+            } else {
+                block.successors.iter().join(", ")
+            },
+            if block.indirect { " (indirect)" } else { "" }
+        );
+
+        for address in block.instructions.iter() {
+            println!("{:>6}  {}", address, analysis.instructions[address]);
+        }
+    }
+
+    if !analysis.invalid.is_empty() {
+        println!();
+        for address in analysis.invalid.iter() {
+            println!("invalid instruction reachable at {}", address);
+        }
+    }
+
+    if !analysis.writes.is_empty() {
+        println!();
+        for write in analysis.writes.iter() {
+            match write.overwrite {
+                Overwrite::Code => {
+                    println!(
+                        "write at {} to {} overwrites code",
+                        write.address, write.dest
+                    )
+                }
+                Overwrite::Unknown => println!(
+                    "write at {} to {} may overwrite code",
+                    write.address, write.dest
+                ),
+            }
+        }
+    }
+
+    println!();
+    match analysis.self_modifying() {
+        Some(true) => println!("self-modifying: yes"),
+        Some(false) => println!("self-modifying: no"),
+        None => println!("self-modifying: unknown"),
+    }
+}
+
+/// Escapes text for a DOT label, left-justifying each line.
+fn label(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{}\\l", line.replace('"', "\\\"")))
+        .collect()
+}
+
+fn print_dot(analysis: &Analysis) {
+    println!("digraph program {{");
+    println!("    node [shape=box, fontname=monospace];");
+
+    let overwriting: BTreeSet<usize> = analysis
+        .writes
+        .iter()
+        .filter(|write| write.overwrite == Overwrite::Code)
+        .map(|write| write.address)
+        .collect();
+
+    for block in analysis.blocks.iter() {
+        let lines: Vec<_> = block
+            .instructions
+            .iter()
+            .map(|address| format!("{:>6}  {}", address, analysis.instructions[address]))
+            .collect();
+
+        let color = if block
+            .instructions
+            .iter()
+            .any(|address| overwriting.contains(address))
+        {
+            ", color=red"
+        } else {
+            ""
+        };
+
+        println!(
+            "    b{} [label=\"{}\"{}];",
+            block.start,
+            label(&lines),
+            color
+        );
+
+        for successor in block.successors.iter() {
+            if analysis.invalid.contains(successor) {
+                println!(
+                    "    invalid{} [label=\"invalid {}\", color=red];",
+                    successor, successor
+                );
+                println!("    b{} -> invalid{};", block.start, successor);
+            } else {
+                println!("    b{} -> b{};", block.start, successor);
+            }
+        }
+
+        if block.indirect {
+            println!("    indirect{} [label=\"?\", shape=circle];", block.start);
+            println!(
+                "    b{} -> indirect{} [style=dashed];",
+                block.start, block.start
+            );
+        }
+    }
+
+    println!("}}");
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let analysis = analyze(&Computer::parse(&input)?, options.dialect);
+
+    if options.dot {
+        print_dot(&analysis);
+    } else {
+        print_text(&analysis);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn analyze_source(source: &str) -> Analysis {
+        analyze(&assemble(source, &Dialect::FULL).unwrap(), &Dialect::FULL)
+    }
+
+    fn blocks(analysis: &Analysis) -> Vec<(Vec<usize>, Vec<usize>, bool)> {
+        analysis
+            .blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.instructions.clone(),
+                    block.successors.clone(),
+                    block.indirect,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn control_flow_graph() {
+        let analysis = analyze_source(
+            "
+                  in [x]
+                  jz [x], #skip
+                  out #1
+            skip: out #2
+                  hlt
+            x:    data 0
+            ",
+        );
+
+        assert_eq!(
+            analysis.instructions.keys().copied().collect::<Vec<_>>(),
+            [0, 2, 5, 7, 9]
+        );
+        assert_eq!(
+            blocks(&analysis),
+            [
+                (vec![0, 2], vec![5, 7], false),
+                (vec![5], vec![7], false),
+                (vec![7, 9], vec![], false),
+            ]
+        );
+        assert!(analysis.invalid.is_empty());
+        assert_eq!(analysis.self_modifying(), Some(false));
+    }
+
+    #[test]
+    fn indirect_jumps() {
+        let analysis = analyze_source(
+            "
+                  jnz #1, [t]
+                  hlt
+            t:    data 3
+            ",
+        );
+
+        // the jump is always taken, so the halt is unreachable
+        assert_eq!(blocks(&analysis), [(vec![0], vec![], true)]);
+        assert_eq!(analysis.self_modifying(), None);
+    }
+
+    #[test]
+    fn invalid_targets() {
+        let analysis = analyze_source(
+            "
+                  jz [x], #100
+                  jz #0, #bad
+            bad:  data 98
+            x:    data 0
+            ",
+        );
+
+        assert_eq!(
+            analysis.invalid.iter().copied().collect::<Vec<_>>(),
+            [6, 100]
+        );
+    }
+
+    #[test]
+    fn writes() {
+        let analysis = analyze_source(
+            "
+                   add #1, #99, [patch]
+            patch: out #0
+                   in [rb+1]
+                   in [x]
+                   hlt
+            x:     data 0
+            ",
+        );
+
+        assert_eq!(
+            analysis
+                .writes
+                .iter()
+                .map(|write| (write.address, write.overwrite))
+                .collect::<Vec<_>>(),
+            [(0, Overwrite::Code), (6, Overwrite::Unknown)]
+        );
+        assert_eq!(analysis.self_modifying(), Some(true));
+
+        let relative = analyze_source("in [rb+1]\nhlt\n");
+        assert_eq!(relative.self_modifying(), None);
+    }
+}
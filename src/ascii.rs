@@ -0,0 +1,208 @@
+use crate::intcode::{Computer, Dialect, Error, Queue, Snapshot, Step};
+use crate::patch::Patch;
+use std::fs::read_to_string;
+use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(parse(from_os_str))]
+    program: PathBuf,
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+/// Something an ASCII program printed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// A line of text, without its newline.
+    Line(String),
+    /// An output outside the ASCII range, such as a puzzle answer.
+    Value(i64),
+}
+
+/// A computer whose input and output are lines of ASCII text.
+#[derive(Clone)]
+pub struct Ascii {
+    computer: Computer,
+    queue: Queue,
+    /// Text printed since the last newline, such as a prompt.
+    partial: String,
+}
+
+// Running
+
+impl Ascii {
+    pub fn new(computer: Computer) -> Ascii {
+        Ascii {
+            computer,
+            queue: Queue::default(),
+            partial: String::new(),
+        }
+    }
+
+    /// Queues a line of input, followed by a newline.
+    pub fn send(&mut self, line: &str) -> Result<(), failure::Error> {
+        if !line.is_ascii() {
+            return Err(failure::err_msg(format!("`{}` is not ASCII", line)));
+        }
+
+        self.queue.input.extend(line.bytes().map(i64::from));
+        self.queue.input.push_back(i64::from(b'\n'));
+
+        Ok(())
+    }
+
+    /// Runs until the program terminates or needs input that has not been sent, returns the
+    /// lines and values it printed. A value ends any partial line before it. The output is
+    /// returned even if the program fails, so that it can be shown along with the error.
+    pub fn run(&mut self) -> (Vec<Output>, Result<Step, Error>) {
+        let result = self.computer.run_io(&mut self.queue);
+        let mut outputs = Vec::new();
+
+        for output in self.queue.output.drain(..) {
+            match output {
+                10 => outputs.push(Output::Line(std::mem::take(&mut self.partial))),
+                0..=127 => self.partial.push(output as u8 as char),
+                _ => {
+                    if !self.partial.is_empty() {
+                        outputs.push(Output::Line(std::mem::take(&mut self.partial)));
+                    }
+                    outputs.push(Output::Value(output));
+                }
+            }
+        }
+
+        (outputs, result)
+    }
+
+    /// Takes the text printed since the last newline, such as a prompt.
+    pub fn take_partial(&mut self) -> String {
+        std::mem::take(&mut self.partial)
+    }
+
+    /// The state of the computer and the input it has not yet read. Any partial line is not
+    /// included.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            computer: self.computer.clone(),
+            input: self.queue.input.iter().copied().collect(),
+        }
+    }
+
+    pub fn restore(snapshot: Snapshot) -> Ascii {
+        let mut ascii = Ascii::new(snapshot.computer);
+        ascii.queue.input.extend(snapshot.input);
+        ascii
+    }
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let program = read_to_string(&options.program)?;
+    let mut ascii = Ascii::new(Computer::new_dialect(
+        &options.patch.apply(Computer::parse(&program)?)?,
+        options.dialect,
+    ));
+
+    loop {
+        let (outputs, step) = ascii.run();
+
+        for output in outputs {
+            match output {
+                Output::Line(line) => println!("{}", line),
+                Output::Value(value) => println!("{}", value),
+            }
+        }
+
+        print!("{}", ascii.take_partial());
+        stdout().flush()?;
+
+        if let Step::Terminated = step? {
+            return Ok(());
+        }
+
+        let mut line = String::new();
+        if stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        ascii.send(line.trim_end_matches(&['\r', '\n'][..]))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_and_values() {
+        // prints "Hi?", echoes a line of input back, then prints 1000
+        let program = [
+            104, 72, 104, 105, 104, 63, 3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 6, 104,
+            1000, 99,
+        ];
+        let mut ascii = Ascii::new(Computer::new(&program));
+
+        let (outputs, step) = ascii.run();
+        assert!(outputs.is_empty());
+        assert!(matches!(step, Ok(Step::NeedsInput)));
+        assert_eq!(ascii.take_partial(), "Hi?");
+
+        ascii.send(" ok").unwrap();
+        let (outputs, step) = ascii.run();
+        assert_eq!(
+            outputs,
+            [Output::Line(" ok".to_string()), Output::Value(1000)]
+        );
+        assert!(matches!(step, Ok(Step::Terminated)));
+        assert_eq!(ascii.take_partial(), "");
+
+        assert!(ascii.send("café").is_err());
+    }
+
+    #[test]
+    fn snapshots_keep_unread_input() {
+        // prints "Hi?" and reads two lines, echoing them back
+        let program = [
+            104, 72, 104, 105, 104, 63, 3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 6, 1001,
+            102, 1, 102, 1007, 102, 2, 101, 1005, 101, 6, 99,
+        ];
+        let mut ascii = Ascii::new(Computer::new(&program));
+        ascii.run().1.unwrap();
+        ascii.take_partial();
+        ascii.send("a").unwrap();
+        ascii.send("b").unwrap();
+
+        let snapshot = ascii.snapshot().to_string();
+        let mut restored = Ascii::restore(snapshot.parse().unwrap());
+
+        let (outputs, step) = restored.run();
+        assert_eq!(
+            outputs,
+            [Output::Line("a".to_string()), Output::Line("b".to_string())]
+        );
+        assert!(matches!(step, Ok(Step::Terminated)));
+    }
+
+    #[test]
+    fn output_survives_errors() {
+        // prints "ok", a newline and a partial "!", then fails on an invalid opcode
+        let program = [104, 111, 104, 107, 104, 10, 104, 33, 98];
+        let mut ascii = Ascii::new(Computer::new(&program));
+
+        let (outputs, step) = ascii.run();
+        assert_eq!(outputs, [Output::Line("ok".to_string())]);
+        assert_eq!(ascii.take_partial(), "!");
+        match step {
+            Err(err) => assert_eq!(err.to_string(), "invalid opcode at 8 (98)"),
+            Ok(_) => panic!("the program should fail"),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use crate::intcode::{Dialect, Instruction, Mode};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{stdin, Read};
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    UnknownMnemonic(String),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    InvalidOperand(String),
+    WrongArity { expected: usize, found: usize },
+    ImmediateDestination,
+    UnsupportedMode(Mode),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::UnknownMnemonic(name) => write!(f, "unknown mnemonic `{}`", name),
+            ErrorKind::UndefinedLabel(name) => write!(f, "undefined label `{}`", name),
+            ErrorKind::DuplicateLabel(name) => write!(f, "duplicate label `{}`", name),
+            ErrorKind::InvalidOperand(text) => write!(f, "invalid operand `{}`", text),
+            ErrorKind::WrongArity { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            ErrorKind::ImmediateDestination => write!(f, "cannot write to an immediate operand"),
+            ErrorKind::UnsupportedMode(mode) => {
+                write!(f, "{:?} mode is not supported by the dialect", mode)
+            }
+        }
+    }
+}
+
+impl failure::Fail for Error {}
+
+/// A literal value, or a reference to the address of a label.
+enum Value {
+    Literal(i64),
+    Label {
+        name: String,
+        negate: bool,
+        column: usize,
+    },
+}
+
+struct Operand {
+    mode: Mode,
+    value: Value,
+}
+
+enum Item {
+    Instruction {
+        instruction: &'static Instruction,
+        operands: Vec<Operand>,
+    },
+    Data(Vec<Value>),
+}
+
+struct Statement {
+    line: usize,
+    item: Item,
+}
+
+// Parsing
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits `text` on commas, returning each trimmed part with its column.
+fn fields(text: &str, column: usize) -> Vec<(&str, usize)> {
+    let mut offset = 0;
+
+    text.split(',')
+        .map(|field| {
+            let start = offset + field.len() - field.trim_start().len();
+            offset += field.len() + 1;
+            (field.trim(), column + start)
+        })
+        .collect()
+}
+
+fn value(text: &str, line: usize, column: usize) -> Result<Value, Error> {
+    let (negate, name) = match text.strip_prefix('-') {
+        Some(name) => (true, name),
+        None => (false, text),
+    };
+
+    if let Ok(literal) = text.parse::<i64>() {
+        Ok(Value::Literal(literal))
+    } else if is_identifier(name) {
+        Ok(Value::Label {
+            name: name.to_string(),
+            negate,
+            column,
+        })
+    } else {
+        Err(Error {
+            line,
+            column,
+            kind: ErrorKind::InvalidOperand(text.to_string()),
+        })
+    }
+}
+
+fn operand(text: &str, line: usize, column: usize) -> Result<Operand, Error> {
+    let invalid = || Error {
+        line,
+        column,
+        kind: ErrorKind::InvalidOperand(text.to_string()),
+    };
+
+    if let Some(immediate) = text.strip_prefix('#') {
+        return Ok(Operand {
+            mode: Mode::Immediate,
+            value: value(immediate, line, column + 1)?,
+        });
+    }
+
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+        .map(str::trim)
+        .ok_or_else(invalid)?;
+
+    match inner.strip_prefix("rb").map(str::trim_start) {
+        Some("") => Ok(Operand {
+            mode: Mode::Relative,
+            value: Value::Literal(0),
+        }),
+        Some(offset) if offset.starts_with('+') || offset.starts_with('-') => {
+            // the sign may be separated from the offset by whitespace, as in `[rb - 5]`
+            let negative = offset.starts_with('-');
+            let offset = offset[1..].trim_start();
+            let column = column + (offset.as_ptr() as usize - text.as_ptr() as usize);
+            let value = match value(offset, line, column)? {
+                Value::Literal(literal) if negative => {
+                    Value::Literal(literal.checked_neg().ok_or_else(invalid)?)
+                }
+                Value::Label {
+                    name,
+                    negate,
+                    column,
+                } => Value::Label {
+                    name,
+                    negate: negate != negative,
+                    column,
+                },
+                value => value,
+            };
+            Ok(Operand {
+                mode: Mode::Relative,
+                value,
+            })
+        }
+        _ => Ok(Operand {
+            mode: Mode::Position,
+            value: value(inner, line, column + 1)?,
+        }),
+    }
+}
+
+/// Parses the source into statements, and collects the address of each label.
+fn parse(source: &str, dialect: &Dialect) -> Result<(Vec<Statement>, HashMap<String, i64>), Error> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut address = 0;
+
+    for (line, text) in source.lines().enumerate().map(|(i, text)| (i + 1, text)) {
+        let mut rest = text.split(';').next().unwrap_or("");
+        let mut column = 1;
+
+        loop {
+            let trimmed = rest.trim_start();
+            column += rest.len() - trimmed.len();
+            rest = trimmed;
+
+            match rest.find(':') {
+                Some(end) if is_identifier(&rest[..end]) => {
+                    let name = &rest[..end];
+                    if labels.insert(name.to_string(), address).is_some() {
+                        return Err(Error {
+                            line,
+                            column,
+                            kind: ErrorKind::DuplicateLabel(name.to_string()),
+                        });
+                    }
+                    column += end + 1;
+                    rest = &rest[end + 1..];
+                }
+                _ => break,
+            }
+        }
+
+        let rest = rest.trim_end();
+        if rest.is_empty() {
+            continue;
+        }
+
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let mnemonic = rest[..end].to_lowercase();
+        let args = fields(&rest[end..], column + end);
+        let args = if args.len() == 1 && args[0].0.is_empty() {
+            Vec::new()
+        } else {
+            args
+        };
+
+        let item = if mnemonic == "data" {
+            Item::Data(
+                args.iter()
+                    .map(|(text, column)| value(text, line, *column))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        } else {
+            let instruction = dialect.find(&mnemonic).ok_or_else(|| Error {
+                line,
+                column,
+                kind: ErrorKind::UnknownMnemonic(rest[..end].to_string()),
+            })?;
+
+            if args.len() != instruction.arity {
+                return Err(Error {
+                    line,
+                    column,
+                    kind: ErrorKind::WrongArity {
+                        expected: instruction.arity,
+                        found: args.len(),
+                    },
+                });
+            }
+
+            let mut operands = Vec::new();
+            for (i, (text, column)) in args.iter().enumerate() {
+                let operand = operand(text, line, *column)?;
+
+                if instruction.dest == Some(i + 1) && operand.mode == Mode::Immediate {
+                    return Err(Error {
+                        line,
+                        column: *column,
+                        kind: ErrorKind::ImmediateDestination,
+                    });
+                }
+
+                if !dialect.modes.contains(&operand.mode) {
+                    return Err(Error {
+                        line,
+                        column: *column,
+                        kind: ErrorKind::UnsupportedMode(operand.mode),
+                    });
+                }
+
+                operands.push(operand);
+            }
+
+            Item::Instruction {
+                instruction,
+                operands,
+            }
+        };
+
+        address += match &item {
+            Item::Instruction { operands, .. } => operands.len() as i64 + 1,
+            Item::Data(values) => values.len() as i64,
+        };
+
+        statements.push(Statement { line, item });
+    }
+
+    Ok((statements, labels))
+}
+
+// Assembling
+
+fn resolve(value: &Value, line: usize, labels: &HashMap<String, i64>) -> Result<i64, Error> {
+    match value {
+        Value::Literal(literal) => Ok(*literal),
+        Value::Label {
+            name,
+            negate,
+            column,
+        } => labels
+            .get(name)
+            // addresses are never negative, so negating one cannot overflow
+            .map(|address| if *negate { -address } else { *address })
+            .ok_or_else(|| Error {
+                line,
+                column: *column,
+                kind: ErrorKind::UndefinedLabel(name.clone()),
+            }),
+    }
+}
+
+/// Assembles source text into a program that can be run by `Computer`.
+pub fn assemble(source: &str, dialect: &Dialect) -> Result<Vec<i64>, Error> {
+    let (statements, labels) = parse(source, dialect)?;
+    let mut program = Vec::new();
+
+    for Statement { line, item } in statements.iter() {
+        match item {
+            Item::Instruction {
+                instruction,
+                operands,
+            } => {
+                let modes: Vec<_> = operands.iter().map(|operand| operand.mode).collect();
+                program.push(instruction.encode(&modes));
+
+                for operand in operands.iter() {
+                    program.push(resolve(&operand.value, *line, &labels)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in values.iter() {
+                    program.push(resolve(value, *line, &labels)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    println!("{}", assemble(&input, options.dialect)?.iter().join(","));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::Computer;
+    use std::iter::empty;
+
+    fn error(source: &str) -> String {
+        assemble(source, &Dialect::FULL).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(
+            assemble("add [4], #-2, [rb+1]\nhlt\n", &Dialect::FULL).unwrap(),
+            [21001, 4, -2, 1, 99]
+        );
+        assert_eq!(
+            assemble("arb #3\nout [rb - 5]\nout [ rb ]\n", &Dialect::FULL).unwrap(),
+            [109, 3, 204, -5, 204, 0]
+        );
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "\
+            ; prints the value at `value` twice, then halts
+            start: out [value]
+                   jz #0, #twice
+            twice: out [value]
+                   hlt
+            value: data 42, start, -twice
+        ";
+
+        let program = assemble(source, &Dialect::FULL).unwrap();
+        assert_eq!(program, [4, 8, 1106, 0, 5, 4, 8, 99, 42, 0, -5]);
+
+        let mut computer = Computer::new(&program);
+        assert_eq!(computer.run_collect(&mut empty()).unwrap(), [42, 42]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("nop\n"), "line 1, column 1: unknown mnemonic `nop`");
+        assert_eq!(
+            error("hlt\n  jz #1, #end\n"),
+            "line 2, column 11: undefined label `end`"
+        );
+        assert_eq!(
+            error("loop: hlt\n  loop: hlt\n"),
+            "line 2, column 3: duplicate label `loop`"
+        );
+        assert_eq!(
+            error("add [0], [1], #2\n"),
+            "line 1, column 15: cannot write to an immediate operand"
+        );
+        assert_eq!(
+            error("out [1], [2]\n"),
+            "line 1, column 1: expected 1 operands, found 2"
+        );
+        assert_eq!(
+            error("out [3x]\n"),
+            "line 1, column 6: invalid operand `3x`"
+        );
+        assert_eq!(
+            error("out [rb--9223372036854775808]\n"),
+            "line 1, column 5: invalid operand `[rb--9223372036854775808]`"
+        );
+        assert_eq!(
+            assemble("out [rb+1]\n", &Dialect::DAY_5)
+                .unwrap_err()
+                .to_string(),
+            "line 1, column 5: Relative mode is not supported by the dialect"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::intcode::{Computer, Dialect, Engine, Queue, Step};
+use crate::q2;
+use std::iter::empty;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    /// The number of times to run each workload with each engine.
+    #[structopt(short, long, default_value = "5")]
+    iterations: u32,
+}
+
+struct Workload {
+    name: &'static str,
+    run: fn(Engine) -> Result<i64, failure::Error>,
+}
+
+// Workloads
+
+const DAY_2: &str = include_str!("../input/2");
+const DAY_9: &str = include_str!("../input/9");
+
+fn day_2_program(engine: Engine) -> Result<i64, failure::Error> {
+    let mut computer = Computer::new_overrides(
+        &Computer::parse(DAY_2)?,
+        &Dialect::DAY_2,
+        vec![(1, 12), (2, 2)],
+    );
+    computer.set_engine(engine);
+
+    Ok(computer.run(&mut empty())?)
+}
+
+fn day_2_search(engine: Engine) -> Result<i64, failure::Error> {
+    q2::search(&Computer::parse(DAY_2)?, engine)
+}
+
+fn day_9_boost(engine: Engine) -> Result<i64, failure::Error> {
+    let mut computer = Computer::new(&Computer::parse(DAY_9)?);
+    computer.set_engine(engine);
+
+    let mut queue = Queue::default();
+    queue.input.push_back(2);
+
+    match computer.run_io(&mut queue)? {
+        Step::Terminated => queue
+            .output
+            .pop_back()
+            .ok_or_else(|| failure::err_msg("no output!")),
+        _ => Err(failure::err_msg("program did not terminate")),
+    }
+}
+
+const WORKLOADS: [Workload; 3] = [
+    Workload {
+        name: "day 2 program",
+        run: day_2_program,
+    },
+    Workload {
+        name: "day 2 search",
+        run: day_2_search,
+    },
+    Workload {
+        name: "day 9 boost",
+        run: day_9_boost,
+    },
+];
+
+// Main
+
+/// Runs a workload repeatedly, returning its result and the fastest time.
+fn measure(
+    workload: &Workload,
+    engine: Engine,
+    iterations: u32,
+) -> Result<(i64, Duration), failure::Error> {
+    let mut result = 0;
+    let mut fastest = Duration::from_secs(u64::MAX);
+
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        result = (workload.run)(engine)?;
+        fastest = fastest.min(start.elapsed());
+    }
+
+    Ok((result, fastest))
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    for workload in WORKLOADS.iter() {
+        let mut baseline = None;
+
+        for engine in Engine::ALL.iter().copied() {
+            let (result, time) = measure(workload, engine, options.iterations)?;
+
+            let (expected, baseline_time) = *baseline.get_or_insert((result, time));
+            if result != expected {
+                return Err(failure::err_msg(format!(
+                    "{}: {:?} produced {}, expected {}",
+                    workload.name, engine, result, expected
+                )));
+            }
+
+            println!(
+                "{:<14} {:<12} {:>10.3?} {:>6.2}x",
+                workload.name,
+                format!("{:?}", engine),
+                time,
+                baseline_time.as_secs_f64() / time.as_secs_f64()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use crate::intcode::{Computer, Dialect, Disassembled, Snapshot, Step};
+use crate::patch::Patch;
+use itertools::Itertools;
+use std::collections::{BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::fs::{read_to_string, write};
+use std::io::{stdin, stdout, Write};
+use std::iter::from_fn;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(parse(from_os_str))]
+    program: PathBuf,
+    #[structopt(short, long)]
+    input: Vec<i64>,
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+/// Why execution stopped.
+enum Stop {
+    Stepped,
+    Output(i64),
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: i64, new: i64 },
+    NeedsInput,
+    Terminated,
+}
+
+struct Debugger {
+    computer: Computer,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    input: VecDeque<i64>,
+    terminated: bool,
+}
+
+const HELP: &str = "\
+step [n]           execute n instructions (s)
+continue           run to a breakpoint, watchpoint or termination (c)
+output             run until the next output (o)
+break <addr>       set a breakpoint (b)
+delete <addr>      remove a breakpoint (d)
+watch <addr>       stop when a memory cell changes (w)
+unwatch <addr>     remove a watchpoint
+input <values..>   queue values for IN instructions (i)
+regs               show the instruction pointer, relative base and memory size (r)
+mem <addr> [len]   dump memory (x)
+list [addr] [n]    disassemble instructions (l)
+save <file>        save the machine state and queued input
+load <file>        restore a saved machine state
+quit               exit the debugger (q)";
+
+/// The most cells `mem` dumps, or instructions `list` shows, at once.
+const MAX_LENGTH: usize = 4096;
+
+// Reading input
+
+fn prompt(text: &str) -> Result<Option<String>, failure::Error> {
+    print!("{}", text);
+    stdout().flush()?;
+
+    let mut line = String::new();
+    if stdin().read_line(&mut line)? == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(line.trim().to_string()))
+    }
+}
+
+fn parse_args(args: &[&str]) -> Result<Vec<i64>, failure::Error> {
+    Ok(args
+        .iter()
+        .map(|arg| arg.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+fn address(value: i64) -> Result<usize, failure::Error> {
+    usize::try_from(value).map_err(|_| failure::err_msg(format!("invalid address {}", value)))
+}
+
+fn length(value: i64) -> Result<usize, failure::Error> {
+    match usize::try_from(value) {
+        Ok(length) if length <= MAX_LENGTH => Ok(length),
+        _ => Err(failure::err_msg(format!(
+            "length {} is not between 0 and {}",
+            value, MAX_LENGTH
+        ))),
+    }
+}
+
+fn end(start: usize, len: usize) -> Result<usize, failure::Error> {
+    start
+        .checked_add(len)
+        .ok_or_else(|| failure::err_msg(format!("address {} is out of range", start)))
+}
+
+// Debugging
+
+impl Debugger {
+    fn new(computer: Computer, input: Vec<i64>) -> Debugger {
+        Debugger {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            input: input.into_iter().collect(),
+            terminated: false,
+        }
+    }
+
+    /// Executes a single instruction, prompting for input if none is queued.
+    fn step(&mut self) -> Result<Stop, failure::Error> {
+        if self.terminated {
+            return Ok(Stop::Terminated);
+        }
+
+        let before: Vec<_> = self
+            .watchpoints
+            .iter()
+            .map(|address| (*address, self.computer.read(*address)))
+            .collect();
+
+        let queue = &mut self.input;
+        let mut input = from_fn(|| {
+            queue
+                .pop_front()
+                .map(Ok)
+                .or_else(|| match prompt("input> ") {
+                    Ok(Some(line)) => Some(line.parse::<i64>().map_err(failure::Error::from)),
+                    Ok(None) => None,
+                    Err(err) => Some(Err(err)),
+                })
+        });
+
+        let step = self.computer.step(&mut input)?;
+
+        if let Some((address, old, new)) = before
+            .into_iter()
+            .map(|(address, old)| (address, old, self.computer.read(address)))
+            .find(|(_, old, new)| old != new)
+        {
+            return Ok(Stop::Watchpoint { address, old, new });
+        }
+
+        Ok(match step {
+            Step::Nothing => Stop::Stepped,
+            Step::Output(output) => Stop::Output(output),
+            Step::NeedsInput => Stop::NeedsInput,
+            Step::Terminated => {
+                self.terminated = true;
+                Stop::Terminated
+            }
+        })
+    }
+
+    /// Executes instructions until `until` returns true for a stop.
+    fn run<F>(&mut self, until: F) -> Result<Stop, failure::Error>
+    where
+        F: Fn(&Stop) -> bool,
+    {
+        loop {
+            let stop = self.step()?;
+
+            if let Stop::Output(output) = stop {
+                println!("output: {}", output);
+            }
+
+            if until(&stop) {
+                return Ok(stop);
+            }
+
+            if self.breakpoints.contains(&self.computer.ip()) {
+                return Ok(Stop::Breakpoint(self.computer.ip()));
+            }
+        }
+    }
+
+    fn report(&self, stop: &Stop) -> Result<(), failure::Error> {
+        match stop {
+            Stop::Stepped | Stop::Output(_) => (),
+            Stop::Breakpoint(address) => println!("breakpoint at {}", address),
+            Stop::Watchpoint { address, old, new } => {
+                println!("watchpoint [{}]: {} -> {}", address, old, new)
+            }
+            Stop::NeedsInput => println!("no input available"),
+            Stop::Terminated => println!("terminated"),
+        }
+
+        if !self.terminated {
+            self.list(self.computer.ip(), 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, mut address: usize, count: usize) -> Result<(), failure::Error> {
+        for _ in 0..count {
+            let line = Disassembled::decode(
+                &self.computer.memory(address..end(address, 4)?),
+                0,
+                self.computer.dialect(),
+            );
+            println!("{:>6}  {}", address, line);
+            address = end(address, line.len())?;
+        }
+
+        Ok(())
+    }
+
+    fn registers(&self) {
+        println!("i = {}", self.computer.ip());
+        println!("relative_base = {}", self.computer.relative_base());
+        println!("memory = {} cells", self.computer.memory_len());
+    }
+
+    fn dump(&self, start: usize, len: usize) -> Result<(), failure::Error> {
+        for (row, cells) in self
+            .computer
+            .memory(start..end(start, len)?)
+            .chunks(8)
+            .enumerate()
+        {
+            println!("{:>6}  {}", start + row * 8, cells.iter().join(" "));
+        }
+
+        Ok(())
+    }
+
+    fn save(&self, path: &str) -> Result<(), failure::Error> {
+        let snapshot = Snapshot {
+            computer: self.computer.clone(),
+            input: self.input.iter().copied().collect(),
+        };
+
+        Ok(write(path, snapshot.to_string())?)
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), failure::Error> {
+        let snapshot: Snapshot = read_to_string(path)?.parse()?;
+
+        self.computer = snapshot.computer;
+        self.input = snapshot.input.into_iter().collect();
+        self.terminated = false;
+        self.list(self.computer.ip(), 1)
+    }
+
+    /// Runs a single command, returns false if the debugger should exit.
+    fn command(&mut self, line: &str) -> Result<bool, failure::Error> {
+        let words: Vec<_> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["save", path] => return self.save(path).map(|_| true),
+            ["load", path] => return self.load(path).map(|_| true),
+            _ => (),
+        }
+
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, parse_args(args)?),
+            None => return Ok(true),
+        };
+
+        match (command, args.as_slice()) {
+            ("s", counts) | ("step", counts) if counts.len() <= 1 => {
+                let mut stop = Stop::Stepped;
+                for _ in 0..counts.first().copied().unwrap_or(1) {
+                    stop = self.step()?;
+                    if let Stop::Output(output) = stop {
+                        println!("output: {}", output);
+                    }
+                    if !matches!(stop, Stop::Stepped | Stop::Output(_)) {
+                        break;
+                    }
+                }
+                self.report(&stop)?;
+            }
+            ("c", []) | ("continue", []) => {
+                let stop = self.run(|stop| !matches!(stop, Stop::Stepped | Stop::Output(_)))?;
+                self.report(&stop)?;
+            }
+            ("o", []) | ("output", []) => {
+                let stop = self.run(|stop| !matches!(stop, Stop::Stepped))?;
+                self.report(&stop)?;
+            }
+            ("b", [value]) | ("break", [value]) => {
+                self.breakpoints.insert(address(*value)?);
+            }
+            ("d", [value]) | ("delete", [value]) => {
+                self.breakpoints.remove(&address(*value)?);
+            }
+            ("w", [value]) | ("watch", [value]) => {
+                self.watchpoints.insert(address(*value)?);
+            }
+            ("unwatch", [value]) => {
+                self.watchpoints.remove(&address(*value)?);
+            }
+            ("i", values) | ("input", values) => self.input.extend(values),
+            ("r", []) | ("regs", []) => self.registers(),
+            ("x", [start]) | ("mem", [start]) => self.dump(address(*start)?, 8)?,
+            ("x", [start, len]) | ("mem", [start, len]) => {
+                self.dump(address(*start)?, length(*len)?)?
+            }
+            ("l", []) | ("list", []) => self.list(self.computer.ip(), 10)?,
+            ("l", [start]) | ("list", [start]) => self.list(address(*start)?, 10)?,
+            ("l", [start, count]) | ("list", [start, count]) => {
+                self.list(address(*start)?, length(*count)?)?
+            }
+            ("h", []) | ("help", []) => println!("{}", HELP),
+            ("q", []) | ("quit", []) => return Ok(false),
+            _ => println!("unknown command `{}`, try `help`", line),
+        }
+
+        Ok(true)
+    }
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let program = read_to_string(&options.program)?;
+    let mut debugger = Debugger::new(
+        Computer::new_dialect(
+            &options.patch.apply(Computer::parse(&program)?)?,
+            options.dialect,
+        ),
+        options.input.clone(),
+    );
+
+    debugger.list(0, 1)?;
+
+    while let Some(line) = prompt("(debug) ")? {
+        match debugger.command(&line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => println!("error: {}", err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(command: &str) -> String {
+        let mut debugger = Debugger::new(Computer::new(&[99]), Vec::new());
+        debugger.command(command).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn addresses_are_checked() {
+        assert_eq!(error("mem -1 8"), "invalid address -1");
+        assert_eq!(error("x 0 -1"), "length -1 is not between 0 and 4096");
+        assert_eq!(error("x 0 5000"), "length 5000 is not between 0 and 4096");
+        assert_eq!(error("break -3"), "invalid address -3");
+    }
+}
@@ -0,0 +1,20 @@
+use crate::intcode::{disassemble, Computer, Dialect};
+use std::io::{stdin, Read};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    for (address, line) in disassemble(&Computer::parse(&input)?, options.dialect) {
+        println!("{:>6}  {}", address, line);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use crate::intcode::{Computer, Dialect, Engine, Instruction, Limits, Mode, Opcode, Queue, Step};
+use itertools::Itertools;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    /// The seed of the first program, each program after it uses the next seed.
+    #[structopt(short, long, default_value = "0")]
+    seed: u64,
+    /// The number of programs to generate.
+    #[structopt(short, long, default_value = "1000")]
+    count: u64,
+    /// The number of instructions in each program.
+    #[structopt(long, default_value = "20")]
+    size: usize,
+    /// The dialect whose instructions and modes are generated.
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+/// A splitmix64 pseudo-random number generator.
+pub struct Rng(u64);
+
+/// The observable result of running a program on one engine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Run {
+    /// The outputs of a program that terminated, or the reason it stopped.
+    pub result: Result<Vec<i64>, String>,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub memory: Vec<i64>,
+}
+
+/// An engine that disagreed with the interpreter.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub engine: Engine,
+    pub expected: Run,
+    pub actual: Run,
+}
+
+/// Generated programs run for at most this long, as they may loop forever.
+const LIMITS: Limits = Limits {
+    steps: Some(10_000),
+    memory: Some(1 << 16),
+    detect_loops: false,
+};
+
+/// The number of cells of data after the code of a generated program.
+const DATA: usize = 8;
+
+// Generating
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// A number in `low..=high`.
+    pub fn between(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next() % (high - low + 1) as u64) as i64
+    }
+
+    /// True with a probability of one in `n`.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+/// Generates an operand for argument `argi` of the instruction at `address`.
+fn operand(
+    rng: &mut Rng,
+    dialect: &Dialect,
+    instruction: &Instruction,
+    argi: usize,
+    address: usize,
+    starts: &[usize],
+    len: usize,
+) -> (Mode, i64) {
+    let dest = instruction.dest == Some(argi);
+    let target = (instruction.is(Opcode::Jnz) || instruction.is(Opcode::Jz)) && argi == 2;
+
+    let modes: Vec<_> = dialect
+        .modes
+        .iter()
+        .copied()
+        .filter(|mode| !dest || *mode != Mode::Immediate)
+        .collect();
+    let mode = rng.choose(&modes);
+
+    let value = match mode {
+        // writes sometimes land on the instruction being executed
+        Mode::Position if dest && rng.one_in(8) => (address + rng.below(4)) as i64,
+        Mode::Position => rng.below(len) as i64,
+        Mode::Relative => rng.between(-4, 8),
+        Mode::Immediate if target && !rng.one_in(8) => rng.choose(starts) as i64,
+        Mode::Immediate if instruction.is(Opcode::Arb) => rng.between(-4, 4),
+        Mode::Immediate => rng.between(-10, 10),
+    };
+
+    (mode, value)
+}
+
+/// Generates a program of `size` valid instructions followed by a halt and some data. Programs
+/// may still fail, for example by reading a negative address.
+pub fn generate(rng: &mut Rng, size: usize, dialect: &Dialect) -> Vec<i64> {
+    let body: Vec<_> = dialect
+        .instructions
+        .iter()
+        .filter(|instruction| !instruction.is(Opcode::Hlt))
+        .collect();
+
+    let instructions: Vec<_> = (0..size)
+        .map(|_| rng.choose(&body))
+        .chain(Some(&Instruction::HLT))
+        .collect();
+
+    let starts: Vec<usize> = instructions
+        .iter()
+        .scan(0, |address, instruction| {
+            let start = *address;
+            *address += instruction.arity + 1;
+            Some(start)
+        })
+        .collect();
+
+    let code_len = starts.last().copied().unwrap_or(0) + 1;
+    let len = code_len + DATA;
+    let mut program = Vec::with_capacity(len);
+
+    for (instruction, address) in instructions.iter().zip(starts.iter()) {
+        let operands: Vec<_> = (1..=instruction.arity)
+            .map(|argi| operand(rng, dialect, instruction, argi, *address, &starts, len))
+            .collect();
+
+        let modes: Vec<_> = operands.iter().map(|(mode, _)| *mode).collect();
+        program.push(instruction.encode(&modes));
+        program.extend(operands.iter().map(|(_, value)| *value));
+    }
+
+    program.extend((0..DATA).map(|_| rng.between(-10, 10)));
+    program
+}
+
+// Checking
+
+/// Runs a program on one engine, feeding it `input`.
+pub fn execute(engine: Engine, dialect: &'static Dialect, program: &[i64], input: &[i64]) -> Run {
+    let mut computer = Computer::new_dialect(program, dialect);
+    computer.set_limits(LIMITS);
+    computer.set_engine(engine);
+
+    let mut queue = Queue::default();
+    queue.input.extend(input);
+
+    let result = match computer.run_io(&mut queue) {
+        Ok(Step::Terminated) => Ok(queue.output.iter().copied().collect()),
+        Ok(_) => Err("needs input".to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+
+    Run {
+        result,
+        ip: computer.ip(),
+        relative_base: computer.relative_base(),
+        memory: computer.memory(0..program.len() + DATA),
+    }
+}
+
+/// Runs a program on every engine, returns the first that disagrees with the interpreter.
+pub fn check(dialect: &'static Dialect, program: &[i64], input: &[i64]) -> Option<Mismatch> {
+    let expected = execute(Engine::Interpreter, dialect, program, input);
+
+    for engine in Engine::ALL.iter().copied() {
+        let actual = execute(engine, dialect, program, input);
+        if actual != expected {
+            return Some(Mismatch {
+                engine,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    None
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    for seed in options.seed..options.seed + options.count {
+        let mut rng = Rng::new(seed);
+        let program = generate(&mut rng, options.size, options.dialect);
+        let input: Vec<_> = (0..4).map(|_| rng.between(-10, 10)).collect();
+
+        if let Some(mismatch) = check(options.dialect, &program, &input) {
+            println!("seed {}: {:?} disagrees", seed, mismatch.engine);
+            println!("program:  {}", program.iter().join(","));
+            println!("input:    {}", input.iter().join(","));
+            println!("expected: {:?}", mismatch.expected);
+            println!("actual:   {:?}", mismatch.actual);
+
+            return Err(failure::err_msg("engines disagree"));
+        }
+    }
+
+    println!("{} programs, engines agree", options.count);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_agree(dialect: &'static Dialect, program: &[i64], input: &[i64]) {
+        if let Some(mismatch) = check(dialect, program, input) {
+            panic!(
+                "{:?} disagrees on {}: expected {:?}, found {:?}",
+                mismatch.engine,
+                program.iter().join(","),
+                mismatch.expected,
+                mismatch.actual
+            );
+        }
+    }
+
+    #[test]
+    fn generated_programs_are_well_formed() {
+        let mut rng = Rng::new(1);
+
+        for _ in 0..100 {
+            let program = generate(&mut rng, 10, &Dialect::FULL);
+            let code = &program[..program.len() - DATA];
+            let lines = crate::intcode::disassemble(code, &Dialect::FULL);
+
+            assert_eq!(lines.len(), 11);
+            assert!(lines
+                .iter()
+                .all(|(_, line)| !matches!(line, crate::intcode::Disassembled::Data(_))));
+        }
+    }
+
+    #[test]
+    fn engines_agree_on_generated_programs() {
+        for dialect in Dialect::ALL.iter().copied() {
+            for seed in 0..200 {
+                let mut rng = Rng::new(seed);
+                let program = generate(&mut rng, 20, dialect);
+                let input: Vec<_> = (0..4).map(|_| rng.between(-10, 10)).collect();
+
+                assert_agree(dialect, &program, &input);
+            }
+        }
+    }
+
+    #[test]
+    fn write_to_current_instruction() {
+        // overwrites its own opcode, then outputs it
+        assert_agree(&Dialect::FULL, &[1101, 1, 1, 0, 4, 0, 99], &[]);
+        // overwrites its own operand through the relative base
+        assert_agree(&Dialect::FULL, &[109, 2, 21101, 5, 5, 1, 4, 3, 99], &[]);
+        // replaces the input instruction with a halt before it is reached
+        assert_agree(&Dialect::FULL, &[1101, 90, 9, 4, 3, 0, 4, 0, 99], &[]);
+    }
+}
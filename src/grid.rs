@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+use std::ops::{Add, Sub};
+
+// Types
+
+/// A position on a grid, with y increasing downwards.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+/// The smallest rectangle containing a set of points, including its edges.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+/// An unbounded grid that only stores the cells that have been set.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    cells: HashMap<Point, T>,
+}
+
+/// An unbounded grid stored as rows, which grows to fit the cells that are set. Cells that have
+/// not been set hold a default value.
+#[derive(Clone, Debug)]
+pub struct Dense<T> {
+    /// The point stored first.
+    origin: Point,
+    width: usize,
+    cells: Vec<T>,
+    default: T,
+    /// The bounds of the cells that have been set, which may be smaller than the storage.
+    bounds: Option<Bounds>,
+}
+
+// Points
+
+impl Point {
+    pub const ORIGIN: Point = Point { x: 0, y: 0 };
+
+    pub fn new(x: i64, y: i64) -> Point {
+        Point { x, y }
+    }
+
+    /// The Manhattan distance to another point.
+    pub fn manhattan(self, other: Point) -> i64 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    /// The four points sharing an edge with this one, in the order of `Direction::ALL`.
+    #[allow(dead_code)] // for the mazes of days 15 and 17, which are not solved yet
+    pub fn neighbours(self) -> impl Iterator<Item = Point> {
+        Direction::ALL
+            .iter()
+            .map(move |direction| self + *direction)
+    }
+
+    /// The eight points sharing an edge or a corner with this one, clockwise from the top left.
+    #[allow(dead_code)] // no solved puzzle looks at diagonal neighbours yet
+    pub fn neighbours8(self) -> impl Iterator<Item = Point> {
+        const OFFSETS: [(i64, i64); 8] = [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+        ];
+
+        OFFSETS.iter().map(move |(x, y)| self + Point::new(*x, *y))
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Add<Direction> for Point {
+    type Output = Point;
+
+    fn add(self, direction: Direction) -> Point {
+        self + direction.offset()
+    }
+}
+
+impl Direction {
+    /// Every direction, clockwise from up.
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn offset(self) -> Point {
+        match self {
+            Direction::Up => Point::new(0, -1),
+            Direction::Right => Point::new(1, 0),
+            Direction::Down => Point::new(0, 1),
+            Direction::Left => Point::new(-1, 0),
+        }
+    }
+
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Right => Direction::Up,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Down,
+        }
+    }
+
+    pub fn turn_right(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+}
+
+// Bounds
+
+impl Bounds {
+    pub fn point(point: Point) -> Bounds {
+        Bounds {
+            min: point,
+            max: point,
+        }
+    }
+
+    /// The smallest bounds containing these bounds and a point.
+    pub fn include(self, point: Point) -> Bounds {
+        Bounds {
+            min: Point::new(self.min.x.min(point.x), self.min.y.min(point.y)),
+            max: Point::new(self.max.x.max(point.x), self.max.y.max(point.y)),
+        }
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x) && (self.min.y..=self.max.y).contains(&point.y)
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1) as usize
+    }
+
+    /// Every point within the bounds, row by row.
+    pub fn points(self) -> impl Iterator<Item = Point> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| Point::new(x, y)))
+    }
+
+    /// Renders each point within the bounds as a character, with a newline after each row.
+    fn render<F: Fn(Point) -> char>(self, glyph: F) -> String {
+        let mut text = String::with_capacity((self.width() + 1) * self.height());
+
+        for point in self.points() {
+            text.push(glyph(point));
+            if point.x == self.max.x {
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+}
+
+/// Calls `cell` with the point and character of each character in the text, with the first
+/// line at y = 0.
+fn parse_text<F>(text: &str, mut cell: F) -> Result<(), failure::Error>
+where
+    F: FnMut(Point, char) -> Result<(), failure::Error>,
+{
+    for (y, line) in text.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            cell(Point::new(x as i64, y as i64), c)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Sparse grids
+
+impl<T> Grid<T> {
+    pub fn new() -> Grid<T> {
+        Grid {
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Parses a grid from lines of text, `cell` returns `None` for characters that leave a
+    /// cell unset, such as empty space.
+    #[allow(dead_code)] // for day 17's camera view, which is not solved yet
+    pub fn parse<F>(text: &str, cell: F) -> Result<Grid<T>, failure::Error>
+    where
+        F: Fn(char) -> Result<Option<T>, failure::Error>,
+    {
+        let mut grid = Grid::new();
+
+        parse_text(text, |point, c| {
+            if let Some(value) = cell(c)? {
+                grid.insert(point, value);
+            }
+            Ok(())
+        })?;
+
+        Ok(grid)
+    }
+
+    pub fn get(&self, point: Point) -> Option<&T> {
+        self.cells.get(&point)
+    }
+
+    /// Sets a cell, returns its previous value.
+    pub fn insert(&mut self, point: Point, value: T) -> Option<T> {
+        self.cells.insert(point, value)
+    }
+
+    /// The number of cells that have been set.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The bounds of the cells that have been set, `None` if there are none.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let mut points = self.cells.keys();
+        let first = Bounds::point(*points.next()?);
+
+        Some(points.fold(first, |bounds, point| bounds.include(*point)))
+    }
+
+    /// Renders the cells within the bounds as lines of text, `glyph` is given `None` for cells
+    /// that have not been set.
+    pub fn render<F>(&self, glyph: F) -> String
+    where
+        F: Fn(Option<&T>) -> char,
+    {
+        match self.bounds() {
+            Some(bounds) => bounds.render(|point| glyph(self.get(point))),
+            None => String::new(),
+        }
+    }
+}
+
+impl<T> Default for Grid<T> {
+    fn default() -> Grid<T> {
+        Grid::new()
+    }
+}
+
+// Dense grids
+
+impl<T: Clone> Dense<T> {
+    #[allow(dead_code)] // for the screens of days 13 and 19, which are not solved yet
+    pub fn new(default: T) -> Dense<T> {
+        Dense {
+            origin: Point::ORIGIN,
+            width: 0,
+            cells: Vec::new(),
+            default,
+            bounds: None,
+        }
+    }
+
+    /// A grid of rows of `width` cells with its top left at the origin, all of which are set.
+    /// Panics unless the cells fill whole rows of a non-zero width, or there are none.
+    pub fn from_cells(width: usize, cells: Vec<T>, default: T) -> Dense<T> {
+        assert!(
+            cells.len().checked_rem(width).unwrap_or(cells.len()) == 0,
+            "{} cells do not fill rows of {}",
+            cells.len(),
+            width
+        );
+
+        let height = cells.len().checked_div(width).unwrap_or(0);
+        let bounds = if height == 0 {
+            None
+        } else {
+            Some(Bounds {
+                min: Point::ORIGIN,
+                max: Point::new(width as i64 - 1, height as i64 - 1),
+            })
+        };
+
+        Dense {
+            origin: Point::ORIGIN,
+            width,
+            cells,
+            default,
+            bounds,
+        }
+    }
+
+    /// Parses a grid from lines of text, which must all be the same length.
+    #[allow(dead_code)] // for day 17's camera view, which is not solved yet
+    pub fn parse<F>(text: &str, default: T, cell: F) -> Result<Dense<T>, failure::Error>
+    where
+        F: Fn(char) -> Result<T, failure::Error>,
+    {
+        let width = text.lines().next().map_or(0, |line| line.chars().count());
+        let mut cells = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let len = line.chars().count();
+            if len != width {
+                return Err(failure::err_msg(format!(
+                    "line {} has {} cells, the first has {}",
+                    i + 1,
+                    len,
+                    width
+                )));
+            }
+
+            for c in line.chars() {
+                cells.push(cell(c)?);
+            }
+        }
+
+        Ok(Dense::from_cells(width, cells, default))
+    }
+
+    /// The bounds of the cells that are stored.
+    fn storage(&self) -> Option<Bounds> {
+        if self.cells.is_empty() {
+            None
+        } else {
+            Some(Bounds {
+                min: self.origin,
+                max: self.origin
+                    + Point::new(
+                        self.width as i64 - 1,
+                        (self.cells.len() / self.width) as i64 - 1,
+                    ),
+            })
+        }
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        let storage = self.storage()?;
+        if storage.contains(point) {
+            let offset = point - self.origin;
+            Some(offset.y as usize * self.width + offset.x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, point: Point) -> &T {
+        match self.index(point) {
+            Some(index) => &self.cells[index],
+            None => &self.default,
+        }
+    }
+
+    #[allow(dead_code)] // for the screens of days 13 and 19, which are not solved yet
+    pub fn set(&mut self, point: Point, value: T) {
+        if self.index(point).is_none() {
+            self.grow(point);
+        }
+
+        let index = self.index(point).unwrap();
+        self.cells[index] = value;
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => bounds.include(point),
+            None => Bounds::point(point),
+        });
+    }
+
+    /// Reallocates to fit a point, leaving as much room again in the direction it grew.
+    fn grow(&mut self, point: Point) {
+        let storage = match self.storage() {
+            Some(storage) => storage,
+            None => {
+                self.origin = point;
+                self.width = 1;
+                self.cells = vec![self.default.clone()];
+                return;
+            }
+        };
+
+        let (width, height) = (storage.width() as i64, storage.height() as i64);
+        let mut grown = storage.include(point);
+        if point.x < storage.min.x {
+            grown.min.x -= width;
+        } else if point.x > storage.max.x {
+            grown.max.x += width;
+        }
+        if point.y < storage.min.y {
+            grown.min.y -= height;
+        } else if point.y > storage.max.y {
+            grown.max.y += height;
+        }
+
+        let mut cells = vec![self.default.clone(); grown.width() * grown.height()];
+        for point in storage.points() {
+            let offset = point - grown.min;
+            cells[offset.y as usize * grown.width() + offset.x as usize] = self.get(point).clone();
+        }
+
+        self.origin = grown.min;
+        self.width = grown.width();
+        self.cells = cells;
+    }
+
+    /// The bounds of the cells that have been set, `None` if there are none.
+    #[allow(dead_code)] // for the screens of days 13 and 19, which are not solved yet
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Renders the cells within the bounds as lines of text.
+    pub fn render<F>(&self, glyph: F) -> String
+    where
+        F: Fn(&T) -> char,
+    {
+        match self.bounds {
+            Some(bounds) => bounds.render(|point| glyph(self.get(point))),
+            None => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directions() {
+        let mut point = Point::ORIGIN;
+        let mut direction = Direction::Up;
+
+        for _ in 0..4 {
+            point = point + direction;
+            direction = direction.turn_right();
+        }
+        assert_eq!(point, Point::ORIGIN);
+        assert_eq!(direction, Direction::Up);
+
+        assert_eq!(Direction::Left.turn_left(), Direction::Down);
+        assert_eq!(Point::new(3, -4).manhattan(Point::ORIGIN), 7);
+    }
+
+    #[test]
+    fn neighbours() {
+        let point = Point::new(2, 5);
+
+        assert_eq!(
+            point.neighbours().collect::<Vec<_>>(),
+            [
+                Point::new(2, 4),
+                Point::new(3, 5),
+                Point::new(2, 6),
+                Point::new(1, 5)
+            ]
+        );
+
+        let around: Vec<_> = point.neighbours8().collect();
+        assert_eq!(around.len(), 8);
+        assert!(point
+            .neighbours()
+            .all(|neighbour| around.contains(&neighbour)));
+        assert!(around
+            .iter()
+            .all(|neighbour| neighbour.manhattan(point) <= 2));
+        assert!(!around.contains(&point));
+    }
+
+    #[test]
+    fn bounds() {
+        let bounds = Bounds::point(Point::new(1, 1)).include(Point::new(-1, 2));
+
+        assert_eq!((bounds.width(), bounds.height()), (3, 2));
+        assert!(bounds.contains(Point::new(0, 2)));
+        assert!(!bounds.contains(Point::new(0, 3)));
+        assert_eq!(
+            bounds.points().collect::<Vec<_>>(),
+            [
+                Point::new(-1, 1),
+                Point::new(0, 1),
+                Point::new(1, 1),
+                Point::new(-1, 2),
+                Point::new(0, 2),
+                Point::new(1, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn sparse_grid() {
+        let mut grid = Grid::new();
+        assert_eq!(grid.len(), 0);
+        assert_eq!(grid.bounds(), None);
+        assert_eq!(grid.render(|_| '#'), "");
+
+        grid.insert(Point::new(-2, -1), 'a');
+        grid.insert(Point::new(1, 0), 'b');
+        assert_eq!(grid.insert(Point::new(1, 0), 'c'), Some('b'));
+
+        assert_eq!(grid.len(), 2);
+        assert_eq!(grid.get(Point::new(1, 0)), Some(&'c'));
+        assert_eq!(grid.get(Point::ORIGIN), None);
+        assert_eq!(
+            grid.bounds(),
+            Some(Bounds {
+                min: Point::new(-2, -1),
+                max: Point::new(1, 0)
+            })
+        );
+        assert_eq!(grid.render(|c| *c.unwrap_or(&'.')), "a...\n...c\n");
+    }
+
+    #[test]
+    fn parse_sparse_grid() {
+        let grid = Grid::parse("#.\n.#\n", |c| match c {
+            '#' => Ok(Some(true)),
+            '.' => Ok(None),
+            _ => Err(failure::err_msg("bad cell")),
+        })
+        .unwrap();
+
+        assert_eq!(grid.len(), 2);
+        assert_eq!(grid.get(Point::new(1, 1)), Some(&true));
+        assert_eq!(
+            grid.render(|cell| if cell.is_some() { '#' } else { '.' }),
+            "#.\n.#\n"
+        );
+
+        assert!(Grid::parse("#?", |c| match c {
+            '#' => Ok(Some(())),
+            _ => Err(failure::err_msg("bad cell")),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn dense_grid_grows_in_every_direction() {
+        let mut grid = Dense::new(0);
+        assert_eq!(grid.bounds(), None);
+        assert_eq!(*grid.get(Point::new(5, 5)), 0);
+
+        let points = [(0, 0), (3, 0), (-4, 1), (1, -7), (-2, 9), (40, -40)];
+        for (i, (x, y)) in points.iter().enumerate() {
+            grid.set(Point::new(*x, *y), i + 1);
+        }
+
+        for (i, (x, y)) in points.iter().enumerate() {
+            assert_eq!(*grid.get(Point::new(*x, *y)), i + 1);
+        }
+        assert_eq!(*grid.get(Point::new(1, 1)), 0);
+        assert_eq!(
+            grid.bounds(),
+            Some(Bounds {
+                min: Point::new(-4, -40),
+                max: Point::new(40, 9)
+            })
+        );
+    }
+
+    #[test]
+    fn dense_grid_parses_and_renders() {
+        let text = "..#\n#..\n";
+        let grid = Dense::parse(text, false, |c| Ok(c == '#')).unwrap();
+
+        assert!(*grid.get(Point::new(2, 0)));
+        assert!(!*grid.get(Point::new(2, 1)));
+        assert!(!*grid.get(Point::new(3, 0)));
+        assert_eq!(grid.render(|cell| if *cell { '#' } else { '.' }), text);
+
+        let error = |text| {
+            Dense::parse(text, false, |c| Ok(c == '#'))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("..\n...\n"), "line 2 has 3 cells, the first has 2");
+        assert_eq!(error("...\n..\n"), "line 2 has 2 cells, the first has 3");
+        assert_eq!(error("...\n.\n..\n"), "line 2 has 1 cells, the first has 3");
+        assert_eq!(error("\n#\n"), "line 2 has 1 cells, the first has 0");
+
+        let empty = Dense::parse("", false, |c| Ok(c == '#')).unwrap();
+        assert_eq!(empty.bounds(), None);
+        assert_eq!(empty.render(|_| '#'), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "3 cells do not fill rows of 0")]
+    fn dense_grid_needs_a_width() {
+        Dense::from_cells(0, vec![1, 2, 3], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "3 cells do not fill rows of 2")]
+    fn dense_grid_needs_whole_rows() {
+        Dense::from_cells(2, vec![1, 2, 3], 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::grid::Dense;
 use itertools::iproduct;
 use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
@@ -15,14 +16,14 @@ impl<'a> Image<'a> {
         string: &'a str,
         width: usize,
         height: usize,
-    ) -> Result<Image, failure::Error> {
+    ) -> Result<Image<'a>, failure::Error> {
         let pixels = string.as_bytes();
         let dimensions = width * height;
         if pixels.len() % dimensions == 0 {
             let layers = pixels.len() / dimensions;
             Ok(Image {
                 pixels: Cow::Borrowed(pixels),
-                offset: '0' as u8,
+                offset: b'0',
                 width,
                 height,
                 layers,
@@ -40,7 +41,7 @@ impl<'a> Image<'a> {
         (0..self.layers).map(move |index| self.layer(index))
     }
 
-    pub fn flatten(&'a self) -> Image {
+    pub fn flatten(&'a self) -> Image<'a> {
         let mut pixels: Vec<_> = self.layer(self.layers - 1).pixels().collect();
 
         for layer in self.layers().rev().skip(1) {
@@ -61,6 +62,14 @@ impl<'a> Image<'a> {
     }
 }
 
+/// How a pixel is displayed: black (0) as a space, and other colours as their digit.
+pub fn glyph(pixel: u8) -> char {
+    match pixel {
+        0 => ' ',
+        _ => (b'0' + pixel) as char,
+    }
+}
+
 pub struct Layer<'a> {
     image: &'a Image<'a>,
     index: usize,
@@ -75,7 +84,7 @@ impl<'a> Layer<'a> {
         iproduct!((0..self.image.height), (0..self.image.width)).map(move |(y, x)| self.pixel(x, y))
     }
 
-    fn overlay(&'a self, on: &mut Vec<u8>) {
+    fn overlay(&'a self, on: &mut [u8]) {
         for (dest, pixel) in on
             .iter_mut()
             .zip(self.pixels())
@@ -88,17 +97,7 @@ impl<'a> Layer<'a> {
 
 impl<'a> Display for Layer<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for y in 0..self.image.height {
-            for x in 0..self.image.width {
-                if self.pixel(x, y) == 0 {
-                    write!(f, " ")?;
-                } else {
-                    write!(f, "{}", self.pixel(x, y))?;
-                }
-            }
-            writeln!(f)?;
-        }
-
-        Ok(())
+        let grid = Dense::from_cells(self.image.width, self.pixels().collect(), 0);
+        write!(f, "{}", grid.render(|pixel| glyph(*pixel)))
     }
 }
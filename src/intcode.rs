@@ -1,19 +1,423 @@
-use std::ops::{Add, Mul};
+mod compiler;
+pub mod trace;
 
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use trace::Event;
+
+#[derive(Clone)]
 pub struct Computer {
-    opcodes: Vec<i64>,
+    memory: Memory,
     i: usize,
     relative_base: i64,
+    trace: Option<Vec<Event>>,
+    limits: Limits,
+    steps: u64,
+    /// A hash of the contents of memory, maintained while detecting loops.
+    fingerprint: u64,
+    /// States seen since input was last consumed, while detecting loops.
+    seen: HashSet<(usize, i64, u64)>,
+    dialect: &'static Dialect,
+    engine: Engine,
+    /// Instructions decoded by `Engine::Decoded`, by address within the program image.
+    decoded: Vec<Option<Decoded>>,
+    /// Blocks compiled by `Engine::Compiled`, by the address they start at within the program
+    /// image.
+    blocks: Vec<Option<Arc<compiler::Block>>>,
+    /// The cells of the program image that compiled blocks were compiled from.
+    code: Vec<bool>,
+}
+
+/// How a computer executes instructions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// Decodes the instruction at the instruction pointer on every step.
+    Interpreter,
+    /// Decodes each instruction once, and decodes it again only if it is overwritten.
+    Decoded,
+    /// Compiles each run of instructions between jumps and I/O into a chain of closures the first
+    /// time it is reached, and reuses it on every later visit. Compiling costs more than
+    /// executing an instruction once, so this only pays off for programs that loop over the same
+    /// code many times, such as day 9's, and is slower than the other engines for short programs
+    /// such as day 2's. Runs as `Decoded` while tracing or detecting loops, which compiled code
+    /// does not support, and for code that the program overwrites after compiling it.
+    Compiled,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 3] = [Engine::Interpreter, Engine::Decoded, Engine::Compiled];
+
+    /// The engine that actually runs under `limits`, as `Compiled` does not detect loops.
+    pub fn under(self, limits: Limits) -> Engine {
+        match self {
+            Engine::Compiled if limits.detect_loops => Engine::Decoded,
+            engine => engine,
+        }
+    }
+}
+
+/// Execution budgets for a computer, `None` is unlimited.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Limits {
+    /// The maximum number of instructions to execute.
+    pub steps: Option<u64>,
+    /// The maximum number of memory cells to allocate.
+    pub memory: Option<usize>,
+    /// Fail if the computer returns to an earlier state without consuming input.
+    pub detect_loops: bool,
 }
 
 pub enum Step {
     Nothing,
     Output(i64),
+    NeedsInput,
     Terminated,
 }
 
 use Step::*;
 
+/// An error raised while executing the instruction at `ip`.
+#[derive(Debug)]
+pub struct Error {
+    pub ip: usize,
+    pub instruction: i64,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidOpcode,
+    InvalidMode { argi: usize, mode: i64 },
+    InvalidDestMode { argi: usize, mode: i64 },
+    NegativeAddress(i64),
+    JumpOutOfRange(i64),
+    Overflow,
+    InputExhausted,
+    StepLimitExceeded(u64),
+    MemoryLimitExceeded(usize),
+    InfiniteLoop,
+    Input(failure::Error),
+    Output(failure::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {} ({})", self.kind, self.ip, self.instruction)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+            ErrorKind::InvalidMode { argi, mode } => {
+                write!(f, "invalid mode {} for argument {}", mode, argi)
+            }
+            ErrorKind::InvalidDestMode { argi, mode } => {
+                write!(f, "invalid dest mode {} for argument {}", mode, argi)
+            }
+            ErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+            ErrorKind::JumpOutOfRange(target) => write!(f, "jump to {} out of range", target),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::InputExhausted => write!(f, "no input"),
+            ErrorKind::StepLimitExceeded(limit) => write!(f, "exceeded {} steps", limit),
+            ErrorKind::MemoryLimitExceeded(address) => {
+                write!(f, "write to {} exceeded memory limit", address)
+            }
+            ErrorKind::InfiniteLoop => write!(f, "infinite loop"),
+            ErrorKind::Input(err) => write!(f, "input failed: {}", err),
+            ErrorKind::Output(err) => write!(f, "output failed: {}", err),
+        }
+    }
+}
+
+impl failure::Fail for Error {}
+
+impl ErrorKind {
+    /// Whether the computer was stopped for exceeding its `Limits`.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::StepLimitExceeded(_)
+                | ErrorKind::MemoryLimitExceeded(_)
+                | ErrorKind::InfiniteLoop
+        )
+    }
+}
+
+/// Input and output for a running computer.
+pub trait Io {
+    /// Returns the next input, or `None` to make the computer yield with `Step::NeedsInput`.
+    /// Implementations may instead block until input is available.
+    fn input(&mut self) -> Result<Option<i64>, failure::Error>;
+
+    fn output(&mut self, value: i64) -> Result<(), failure::Error>;
+}
+
+/// I/O backed by in-memory queues, yields when the input queue is empty.
+#[derive(Clone, Default)]
+pub struct Queue {
+    pub input: VecDeque<i64>,
+    pub output: VecDeque<i64>,
+}
+
+impl Io for Queue {
+    fn input(&mut self) -> Result<Option<i64>, failure::Error> {
+        Ok(self.input.pop_front())
+    }
+
+    fn output(&mut self, value: i64) -> Result<(), failure::Error> {
+        self.output.push_back(value);
+        Ok(())
+    }
+}
+
+/// The built-in Intcode instructions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+/// What an instruction does when executed.
+#[derive(Copy, Clone, Debug)]
+pub enum Handler {
+    Builtin(Opcode),
+    /// Stores a function of the first two arguments in the third, `None` if it overflows.
+    Operator(fn(i64, i64) -> Option<i64>),
+}
+
+/// The definition of an instruction, identified by the lowest two digits of an opcode.
+#[derive(Copy, Clone, Debug)]
+pub struct Instruction {
+    pub code: i64,
+    pub mnemonic: &'static str,
+    /// The number of arguments following the opcode.
+    pub arity: usize,
+    /// The argument that is written to, if any.
+    pub dest: Option<usize>,
+    pub handler: Handler,
+}
+
+impl Instruction {
+    const fn builtin(
+        code: i64,
+        mnemonic: &'static str,
+        arity: usize,
+        dest: Option<usize>,
+        opcode: Opcode,
+    ) -> Instruction {
+        Instruction {
+            code,
+            mnemonic,
+            arity,
+            dest,
+            handler: Handler::Builtin(opcode),
+        }
+    }
+
+    pub const ADD: Instruction = Instruction::builtin(1, "add", 3, Some(3), Opcode::Add);
+    pub const MUL: Instruction = Instruction::builtin(2, "mul", 3, Some(3), Opcode::Mul);
+    pub const IN: Instruction = Instruction::builtin(3, "in", 1, Some(1), Opcode::In);
+    pub const OUT: Instruction = Instruction::builtin(4, "out", 1, None, Opcode::Out);
+    pub const JNZ: Instruction = Instruction::builtin(5, "jnz", 2, None, Opcode::Jnz);
+    pub const JZ: Instruction = Instruction::builtin(6, "jz", 2, None, Opcode::Jz);
+    pub const LT: Instruction = Instruction::builtin(7, "lt", 3, Some(3), Opcode::Lt);
+    pub const EQ: Instruction = Instruction::builtin(8, "eq", 3, Some(3), Opcode::Eq);
+    pub const ARB: Instruction = Instruction::builtin(9, "arb", 1, None, Opcode::Arb);
+    pub const HLT: Instruction = Instruction::builtin(99, "hlt", 0, None, Opcode::Hlt);
+
+    /// Whether this is the built-in instruction `opcode`.
+    pub fn is(&self, opcode: Opcode) -> bool {
+        matches!(self.handler, Handler::Builtin(builtin) if builtin == opcode)
+    }
+
+    /// Encodes this instruction with the given argument modes.
+    pub fn encode(&self, modes: &[Mode]) -> i64 {
+        modes
+            .iter()
+            .enumerate()
+            .fold(self.code, |opcode, (i, mode)| {
+                opcode + mode.digit() * i64::pow(10, i as u32 + 2)
+            })
+    }
+}
+
+/// A set of instructions and addressing modes, anything else is rejected as invalid.
+pub struct Dialect {
+    pub name: &'static str,
+    pub instructions: &'static [Instruction],
+    pub modes: &'static [Mode],
+}
+
+impl Dialect {
+    /// Day 2, with only addition and multiplication.
+    pub const DAY_2: Dialect = Dialect {
+        name: "day2",
+        instructions: &[Instruction::ADD, Instruction::MUL, Instruction::HLT],
+        modes: &[Mode::Position],
+    };
+
+    /// Day 5, which adds I/O, jumps, comparisons and immediate mode.
+    pub const DAY_5: Dialect = Dialect {
+        name: "day5",
+        instructions: &[
+            Instruction::ADD,
+            Instruction::MUL,
+            Instruction::IN,
+            Instruction::OUT,
+            Instruction::JNZ,
+            Instruction::JZ,
+            Instruction::LT,
+            Instruction::EQ,
+            Instruction::HLT,
+        ],
+        modes: &[Mode::Position, Mode::Immediate],
+    };
+
+    /// Day 9, the complete Intcode computer.
+    pub const FULL: Dialect = Dialect {
+        name: "full",
+        instructions: &[
+            Instruction::ADD,
+            Instruction::MUL,
+            Instruction::IN,
+            Instruction::OUT,
+            Instruction::JNZ,
+            Instruction::JZ,
+            Instruction::LT,
+            Instruction::EQ,
+            Instruction::ARB,
+            Instruction::HLT,
+        ],
+        modes: &[Mode::Position, Mode::Immediate, Mode::Relative],
+    };
+
+    /// The complete computer with experimental bitwise instructions.
+    pub const EXTENDED: Dialect = Dialect {
+        name: "extended",
+        instructions: &[
+            Instruction::ADD,
+            Instruction::MUL,
+            Instruction::IN,
+            Instruction::OUT,
+            Instruction::JNZ,
+            Instruction::JZ,
+            Instruction::LT,
+            Instruction::EQ,
+            Instruction::ARB,
+            Instruction {
+                code: 10,
+                mnemonic: "and",
+                arity: 3,
+                dest: Some(3),
+                handler: Handler::Operator(|a, b| Some(a & b)),
+            },
+            Instruction {
+                code: 11,
+                mnemonic: "or",
+                arity: 3,
+                dest: Some(3),
+                handler: Handler::Operator(|a, b| Some(a | b)),
+            },
+            Instruction {
+                code: 12,
+                mnemonic: "xor",
+                arity: 3,
+                dest: Some(3),
+                handler: Handler::Operator(|a, b| Some(a ^ b)),
+            },
+            Instruction::HLT,
+        ],
+        modes: &[Mode::Position, Mode::Immediate, Mode::Relative],
+    };
+
+    pub const ALL: [&'static Dialect; 4] = [
+        &Dialect::DAY_2,
+        &Dialect::DAY_5,
+        &Dialect::FULL,
+        &Dialect::EXTENDED,
+    ];
+
+    /// Finds a dialect by name, for command line options.
+    pub fn named(name: &str) -> Result<&'static Dialect, failure::Error> {
+        Dialect::ALL
+            .iter()
+            .copied()
+            .find(|dialect| dialect.name == name)
+            .ok_or_else(|| {
+                failure::err_msg(format!(
+                    "unknown dialect `{}`, expected one of {}",
+                    name,
+                    Dialect::ALL.iter().map(|dialect| dialect.name).join(", ")
+                ))
+            })
+    }
+
+    /// Finds the instruction for an opcode.
+    pub fn decode(&self, opcode: i64) -> Option<&'static Instruction> {
+        self.instructions
+            .iter()
+            .find(|instruction| instruction.code == opcode % 100)
+    }
+
+    pub fn find(&self, mnemonic: &str) -> Option<&'static Instruction> {
+        self.instructions
+            .iter()
+            .find(|instruction| instruction.mnemonic == mnemonic)
+    }
+
+    /// Decodes the mode of argument `argi` of `opcode`, returning the mode digit if it is not
+    /// supported.
+    pub fn mode(&self, opcode: i64, argi: usize) -> Result<Mode, i64> {
+        let digit = opcode / (i64::pow(10, argi as u32 + 1)) % 10;
+        Mode::decode(opcode, argi)
+            .ok()
+            .filter(|mode| self.modes.contains(mode))
+            .ok_or(digit)
+    }
+}
+
+/// The addressing mode of an argument.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    /// Decodes the mode of argument `argi` of `opcode`.
+    pub fn decode(opcode: i64, argi: usize) -> Result<Mode, i64> {
+        match opcode / (i64::pow(10, argi as u32 + 1)) % 10 {
+            0 => Ok(Mode::Position),
+            1 => Ok(Mode::Immediate),
+            2 => Ok(Mode::Relative),
+            mode => Err(mode),
+        }
+    }
+
+    pub fn digit(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
 impl Computer {
     pub fn parse(input: &str) -> Result<Vec<i64>, std::num::ParseIntError> {
         input
@@ -23,153 +427,1458 @@ impl Computer {
             .collect::<Result<Vec<_>, _>>()
     }
 
-    pub fn new(opcodes: &Vec<i64>) -> Computer {
+    pub fn new(opcodes: &[i64]) -> Computer {
+        Computer::new_dialect(opcodes, &Dialect::FULL)
+    }
+
+    /// Creates a computer that only accepts the instructions and modes of `dialect`.
+    pub fn new_dialect(opcodes: &[i64], dialect: &'static Dialect) -> Computer {
         Computer {
-            opcodes: opcodes.clone(),
+            memory: Memory::new(opcodes),
             i: 0,
             relative_base: 0,
+            trace: None,
+            limits: Limits::default(),
+            steps: 0,
+            fingerprint: 0,
+            seen: HashSet::new(),
+            dialect,
+            engine: Engine::Interpreter,
+            decoded: Vec::new(),
+            blocks: Vec::new(),
+            code: Vec::new(),
         }
     }
 
-    pub fn new_overrides(opcodes: &Vec<i64>, overrides: Vec<(usize, i64)>) -> Computer {
-        let mut computer = Computer::new(opcodes);
+    pub fn new_overrides(
+        opcodes: &[i64],
+        dialect: &'static Dialect,
+        overrides: Vec<(usize, i64)>,
+    ) -> Computer {
+        let mut computer = Computer::new_dialect(opcodes, dialect);
 
         for (i, val) in overrides.iter() {
-            computer.opcodes[*i] = *val;
+            computer.write(*i, *val);
         }
 
         computer
     }
 
-    /// Runs the computer to completion, returns the value at opcode 0.
-    pub fn run<Input>(&mut self, input: &mut Input) -> Result<i64, failure::Error>
+    /// Clones this computer, including its execution state, with memory cells overridden.
+    pub fn fork(&self, overrides: &[(usize, i64)]) -> Computer {
+        let mut computer = self.clone();
+
+        for (i, val) in overrides.iter() {
+            computer.write(*i, *val);
+        }
+
+        computer
+    }
+
+    /// Limits execution, steps already executed count towards the limit.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.seen.clear();
+        self.fingerprint = self.memory.cells().fold(0, |hash, (address, val)| {
+            hash.wrapping_add(cell_hash(address, val))
+        });
+    }
+
+    /// Selects how instructions are executed, the results are the same for every engine.
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.decoded = match engine {
+            Engine::Interpreter => Vec::new(),
+            Engine::Decoded | Engine::Compiled => (0..self.memory.image.len())
+                .map(|address| self.decode(address))
+                .collect(),
+        };
+        self.blocks = Vec::new();
+        self.code = Vec::new();
+
+        if engine == Engine::Compiled {
+            self.blocks = vec![None; self.memory.image.len()];
+            self.code = vec![false; self.memory.image.len()];
+        }
+    }
+
+    /// The engine that actually executes instructions, which is `Decoded` for a compiled computer
+    /// that is tracing or detecting loops.
+    pub fn engine(&self) -> Engine {
+        match self.engine {
+            Engine::Compiled if self.trace.is_some() => Engine::Decoded,
+            engine => engine.under(self.limits),
+        }
+    }
+
+    pub fn dialect(&self) -> &'static Dialect {
+        self.dialect
+    }
+
+    /// The address of the next instruction to execute.
+    pub fn ip(&self) -> usize {
+        self.i
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// The number of memory cells that have been allocated.
+    pub fn memory_len(&self) -> usize {
+        self.memory.allocated()
+    }
+
+    /// Reads a memory cell, unallocated cells are zero.
+    pub fn read(&self, address: usize) -> i64 {
+        self.memory.get(address)
+    }
+
+    /// Reads a range of memory cells, unallocated cells are zero.
+    pub fn memory(&self, range: Range<usize>) -> Vec<i64> {
+        range.map(|address| self.read(address)).collect()
+    }
+
+    /// Runs the computer to completion, discarding its outputs, returns the value at opcode 0.
+    pub fn run<Input>(&mut self, input: &mut Input) -> Result<i64, Error>
+    where
+        Input: Iterator<Item = Result<i64, failure::Error>>,
+    {
+        self.run_sink(input, |_| Ok(()))
+    }
+
+    /// Runs the computer to completion, sending each output to `sink`, returns the value at
+    /// opcode 0.
+    pub fn run_sink<Input, Sink>(&mut self, input: &mut Input, mut sink: Sink) -> Result<i64, Error>
     where
         Input: Iterator<Item = Result<i64, failure::Error>>,
+        Sink: FnMut(i64) -> Result<(), failure::Error>,
     {
         loop {
+            self.run_compiled()?;
+
             match self.step(input)? {
                 Nothing => (),
-                Output(output) => println!("{}", output),
-                Terminated => return Ok(self.opcodes[0]),
+                Output(output) => {
+                    sink(output).map_err(|err| self.error(ErrorKind::Output(err)))?;
+                }
+                NeedsInput => return Err(self.error(ErrorKind::InputExhausted)),
+                Terminated => return Ok(self.read(0)),
             }
         }
     }
 
-    /// Runs the computer a single step.
-    pub fn step<Input>(&mut self, input: &mut Input) -> Result<Step, failure::Error>
+    /// Runs the computer to completion, returns its outputs.
+    pub fn run_collect<Input>(&mut self, input: &mut Input) -> Result<Vec<i64>, Error>
     where
         Input: Iterator<Item = Result<i64, failure::Error>>,
     {
-        let opcode = self.opcodes[self.i];
-
-        match opcode % 100 {
-            1 => self.operator_step(i64::add),
-            2 => self.operator_step(i64::mul),
-            3 => self.input_step(input),
-            4 => self.output_step(),
-            5 => self.jump_step(i64::ne),
-            6 => self.jump_step(i64::eq),
-            7 => self.store_step(i64::lt),
-            8 => self.store_step(i64::eq),
-            9 => self.relative_base_step(),
-            99 => Ok(Terminated),
-            _ => Err(failure::err_msg(format!(
-                "invalid opcode {}",
-                self.opcodes[self.i]
-            ))),
-        }
-    }
-
-    fn input_step<Input>(&mut self, input: &mut Input) -> Result<Step, failure::Error>
+        let mut outputs = Vec::new();
+        self.run_sink(input, |output| {
+            outputs.push(output);
+            Ok(())
+        })?;
+
+        Ok(outputs)
+    }
+
+    /// Runs the computer until it has produced `count` outputs or terminates, returns the
+    /// outputs. The computer can be run again to continue after the last output.
+    pub fn run_take<Input>(&mut self, input: &mut Input, count: usize) -> Result<Vec<i64>, Error>
+    where
+        Input: Iterator<Item = Result<i64, failure::Error>>,
+    {
+        let mut outputs = Vec::new();
+
+        while outputs.len() < count {
+            self.run_compiled()?;
+
+            match self.step(input)? {
+                Nothing => (),
+                Output(output) => outputs.push(output),
+                NeedsInput => return Err(self.error(ErrorKind::InputExhausted)),
+                Terminated => break,
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Runs the computer until it terminates or needs input that is not available.
+    pub fn run_io<I: Io>(&mut self, io: &mut I) -> Result<Step, Error> {
+        loop {
+            self.run_compiled()?;
+
+            match self.step_io(io)? {
+                Nothing | Output(_) => (),
+                step => return Ok(step),
+            }
+        }
+    }
+
+    /// Starts recording every executed instruction.
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Takes the instructions recorded since tracing was enabled or last taken.
+    pub fn take_trace(&mut self) -> Vec<Event> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Runs the computer a single step, yields with `NeedsInput` if `input` is exhausted.
+    pub fn step<Input>(&mut self, input: &mut Input) -> Result<Step, Error>
     where
         Input: Iterator<Item = Result<i64, failure::Error>>,
     {
-        let val = input
-            .next()
-            .ok_or_else(|| failure::err_msg(format!("no input at {}", self.i)))??;
+        self.step_with(&mut || input.next().transpose())
+    }
+
+    /// Runs the computer a single step, sending any output to `io`.
+    pub fn step_io<I: Io>(&mut self, io: &mut I) -> Result<Step, Error> {
+        let step = self.step_with(&mut || io.input())?;
+
+        if let Output(output) = step {
+            io.output(output)
+                .map_err(|err| self.error(ErrorKind::Output(err)))?;
+        }
+
+        Ok(step)
+    }
+
+    /// Runs compiled blocks until reaching an instruction that must be stepped.
+    fn run_compiled(&mut self) -> Result<(), Error> {
+        while self.engine() == Engine::Compiled {
+            let start = self.i;
+
+            // the block is taken while it runs, and put back unless it overwrote compiled code
+            let block = match self.blocks.get_mut(start).map(Option::take) {
+                Some(Some(block)) => block,
+                Some(None) => Arc::new(compiler::compile(self, start)),
+                None => return Ok(()),
+            };
+
+            let exceeds_limit = match self.limits.steps {
+                Some(limit) => self.steps + block.len() as u64 > limit,
+                None => false,
+            };
+            let result = if exceeds_limit {
+                Ok(false)
+            } else {
+                block.run(self)
+            };
+
+            if self.engine == Engine::Compiled {
+                self.blocks[start] = Some(block);
+            }
+
+            if !result? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step_with<Input>(&mut self, input: &mut Input) -> Result<Step, Error>
+    where
+        Input: FnMut() -> Result<Option<i64>, failure::Error>,
+    {
+        if let Some(limit) = self.limits.steps {
+            if self.steps >= limit {
+                return Err(self.error(ErrorKind::StepLimitExceeded(limit)));
+            }
+        }
+
+        let state = (self.i, self.relative_base, self.fingerprint);
+
+        if let Some(mut trace) = self.trace.take() {
+            trace.push(self.trace_event());
+            self.trace = Some(trace);
+        }
+
+        let step = self.execute(input)?;
+
+        match step {
+            Nothing | Output(_) => {
+                self.steps += 1;
+                if self.limits.detect_loops && !self.seen.insert(state) {
+                    return Err(Error {
+                        ip: state.0,
+                        instruction: self.read(state.0),
+                        kind: ErrorKind::InfiniteLoop,
+                    });
+                }
+            }
+            NeedsInput => self.seen.clear(),
+            Terminated => (),
+        }
+
+        if let (Output(output), Some(event)) = (&step, self.traced()) {
+            event.output = Some(*output);
+        }
+
+        // nothing executed, so there is nothing to record
+        if let (NeedsInput, Some(trace)) = (&step, self.trace.as_mut()) {
+            trace.pop();
+        }
+
+        Ok(step)
+    }
+
+    fn execute<Input>(&mut self, input: &mut Input) -> Result<Step, Error>
+    where
+        Input: FnMut() -> Result<Option<i64>, failure::Error>,
+    {
+        if self.engine != Engine::Interpreter {
+            let decoded = match self.decoded.get(self.i) {
+                Some(Some(decoded)) => Some(*decoded),
+                Some(None) => {
+                    let decoded = self.decode(self.i);
+                    self.decoded[self.i] = decoded;
+                    decoded
+                }
+                None => None,
+            };
+
+            // anything that cannot be decoded is left to the interpreter, to report errors
+            if let Some(decoded) = decoded {
+                return self.execute_decoded(decoded, input);
+            }
+        }
+
+        let opcode = self.read(self.i);
+
+        let handler = match self.dialect.decode(opcode) {
+            Some(instruction) => instruction.handler,
+            None => return Err(self.error(ErrorKind::InvalidOpcode)),
+        };
+
+        match handler {
+            Handler::Builtin(Opcode::Add) => self.operator_step(i64::checked_add),
+            Handler::Builtin(Opcode::Mul) => self.operator_step(i64::checked_mul),
+            Handler::Builtin(Opcode::In) => self.input_step(input),
+            Handler::Builtin(Opcode::Out) => self.output_step(),
+            Handler::Builtin(Opcode::Jnz) => self.jump_step(i64::ne),
+            Handler::Builtin(Opcode::Jz) => self.jump_step(i64::eq),
+            Handler::Builtin(Opcode::Lt) => self.store_step(i64::lt),
+            Handler::Builtin(Opcode::Eq) => self.store_step(i64::eq),
+            Handler::Builtin(Opcode::Arb) => self.relative_base_step(),
+            Handler::Builtin(Opcode::Hlt) => Ok(Terminated),
+            Handler::Operator(operator) => self.operator_step(operator),
+        }
+    }
+
+    fn execute_decoded<Input>(&mut self, decoded: Decoded, input: &mut Input) -> Result<Step, Error>
+    where
+        Input: FnMut() -> Result<Option<i64>, failure::Error>,
+    {
+        let [a, b, c] = decoded.operands;
+
+        match decoded.instruction.handler {
+            Handler::Builtin(Opcode::Add) => self.operate(a, b, c, i64::checked_add)?,
+            Handler::Builtin(Opcode::Mul) => self.operate(a, b, c, i64::checked_mul)?,
+            Handler::Builtin(Opcode::In) => match self.input(input)? {
+                Some(val) => {
+                    self.store(self.operand_dest(a)?, val)?;
+                    self.seen.clear();
+                }
+                None => return Ok(NeedsInput),
+            },
+            Handler::Builtin(Opcode::Out) => {
+                let val = self.load(a)?;
+                self.i += 2;
+                return Ok(Output(val));
+            }
+            Handler::Builtin(Opcode::Jnz) | Handler::Builtin(Opcode::Jz) => {
+                if (self.load(a)? != 0) == decoded.instruction.is(Opcode::Jnz) {
+                    let target = self.load(b)?;
+                    return self.jump(target);
+                }
+            }
+            Handler::Builtin(Opcode::Lt) => {
+                let val = self.load(a)? < self.load(b)?;
+                self.store(self.operand_dest(c)?, val as i64)?;
+            }
+            Handler::Builtin(Opcode::Eq) => {
+                let val = self.load(a)? == self.load(b)?;
+                self.store(self.operand_dest(c)?, val as i64)?;
+            }
+            Handler::Builtin(Opcode::Arb) => {
+                let offset = self.load(a)?;
+                self.adjust_relative_base(offset)?;
+            }
+            Handler::Builtin(Opcode::Hlt) => return Ok(Terminated),
+            Handler::Operator(operator) => self.operate(a, b, c, operator)?,
+        }
+
+        self.i += decoded.instruction.arity + 1;
+        Ok(Nothing)
+    }
+
+    /// Reads a value from `input`, yields `None` if there is none available.
+    fn input<Input>(&mut self, input: &mut Input) -> Result<Option<i64>, Error>
+    where
+        Input: FnMut() -> Result<Option<i64>, failure::Error>,
+    {
+        let val = match input() {
+            Ok(Some(val)) => val,
+            Ok(None) => return Ok(None),
+            Err(err) => return Err(self.error(ErrorKind::Input(err))),
+        };
+
+        if let Some(event) = self.traced() {
+            event.input = Some(val);
+        }
+
+        Ok(Some(val))
+    }
+
+    fn input_step<Input>(&mut self, input: &mut Input) -> Result<Step, Error>
+    where
+        Input: FnMut() -> Result<Option<i64>, failure::Error>,
+    {
+        let val = match self.input(input)? {
+            Some(val) => val,
+            None => return Ok(NeedsInput),
+        };
 
         self.set(1, val)?;
-        self.i = self.i + 2;
+        self.i += 2;
+        self.seen.clear();
 
         Ok(Nothing)
     }
 
-    fn output_step(&mut self) -> Result<Step, failure::Error> {
+    fn output_step(&mut self) -> Result<Step, Error> {
         let res = self.arg(1)?;
-        self.i = self.i + 2;
+        self.i += 2;
         Ok(Output(res))
     }
 
-    fn operator_step<F>(&mut self, operator: F) -> Result<Step, failure::Error>
+    fn operator_step<F>(&mut self, operator: F) -> Result<Step, Error>
     where
-        F: Fn(i64, i64) -> i64,
+        F: Fn(i64, i64) -> Option<i64>,
     {
-        self.set(3, operator(self.arg(1)?, self.arg(2)?))?;
-        self.i = self.i + 4;
+        let val = self.checked(operator(self.arg(1)?, self.arg(2)?))?;
+        self.set(3, val)?;
+        self.i += 4;
         Ok(Nothing)
     }
 
-    fn jump_step<F>(&mut self, operator: F) -> Result<Step, failure::Error>
+    fn jump_step<F>(&mut self, operator: F) -> Result<Step, Error>
     where
         F: Fn(&i64, &i64) -> bool,
     {
-        self.i = if operator(&self.arg(1)?, &0) {
-            self.arg(2)? as usize
-        } else {
-            self.i + 3
-        };
+        if operator(&self.arg(1)?, &0) {
+            let target = self.arg(2)?;
+            return self.jump(target);
+        }
+
+        self.i += 3;
+        Ok(Nothing)
+    }
+
+    fn jump(&mut self, target: i64) -> Result<Step, Error> {
+        if target < 0 || target as usize >= self.memory.len() {
+            return Err(self.error(ErrorKind::JumpOutOfRange(target)));
+        }
 
+        self.i = target as usize;
         Ok(Nothing)
     }
 
-    fn store_step<F>(&mut self, operator: F) -> Result<Step, failure::Error>
+    fn store_step<F>(&mut self, operator: F) -> Result<Step, Error>
     where
         F: Fn(&i64, &i64) -> bool,
     {
         self.set(3, operator(&self.arg(1)?, &self.arg(2)?) as i64)?;
-        self.i = self.i + 4;
+        self.i += 4;
         Ok(Nothing)
     }
 
-    fn relative_base_step(&mut self) -> Result<Step, failure::Error> {
-        self.relative_base += self.arg(1)?;
-        self.i = self.i + 2;
+    fn relative_base_step(&mut self) -> Result<Step, Error> {
+        self.adjust_relative_base(self.arg(1)?)?;
+        self.i += 2;
         Ok(Nothing)
     }
 
-    fn mode(&self, argi: usize) -> i64 {
-        self.opcodes[self.i] / (i64::pow(10, argi as u32 + 1)) % 10
+    fn adjust_relative_base(&mut self, offset: i64) -> Result<(), Error> {
+        self.relative_base = self.checked(self.relative_base.checked_add(offset))?;
+        Ok(())
     }
 
-    fn arg(&self, argi: usize) -> Result<i64, failure::Error> {
-        let val = self.opcodes[self.i + argi];
-        let mode = self.mode(argi);
-        match mode {
-            0 => Ok(self.opcodes[val as usize]),
-            1 => Ok(val),
-            2 => Ok(self.opcodes[(val + self.relative_base) as usize]),
-            _ => Err(failure::err_msg(format!("invalid arg mode {}", mode))),
+    fn write(&mut self, address: usize, val: i64) {
+        if self.limits.detect_loops {
+            self.fingerprint = self
+                .fingerprint
+                .wrapping_sub(cell_hash(address, self.memory.get(address)))
+                .wrapping_add(cell_hash(address, val));
+        }
+
+        self.memory.set(address, val);
+
+        // compiled code has been overwritten, so the program is self-modifying
+        if self.code.get(address) == Some(&true) {
+            self.engine = Engine::Decoded;
+            self.blocks = Vec::new();
+            self.code = Vec::new();
+        }
+
+        // the instruction at an address is decoded from it and the three cells after it
+        for address in address.saturating_sub(3)..=address {
+            if let Some(decoded) = self.decoded.get_mut(address) {
+                *decoded = None;
+            }
         }
     }
 
-    fn dest(&self, argi: usize) -> Result<usize, failure::Error> {
-        let mode = self.mode(argi);
-        match mode {
-            0 => Ok(self.opcodes[self.i + argi as usize] as usize),
-            2 => Ok((self.opcodes[self.i + argi as usize] + self.relative_base) as usize),
-            _ => Err(failure::err_msg(format!("invalid dest mode {}", mode))),
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error {
+            ip: self.i,
+            instruction: self.read(self.i),
+            kind,
         }
     }
 
-    fn set(&mut self, argi: usize, val: i64) -> Result<(), failure::Error> {
-        let dest = self.dest(argi)?;
+    fn mode(&self, argi: usize) -> Result<Mode, Error> {
+        self.dialect
+            .mode(self.read(self.i), argi)
+            .map_err(|mode| self.error(ErrorKind::InvalidMode { argi, mode }))
+    }
+
+    /// Fails with `ErrorKind::Overflow` if a calculation overflowed.
+    fn checked(&self, val: Option<i64>) -> Result<i64, Error> {
+        val.ok_or_else(|| self.error(ErrorKind::Overflow))
+    }
 
-        if self.opcodes.len() <= dest {
-            self.opcodes.resize(dest + 1, 0);
+    /// The address at `offset` from the relative base.
+    fn relative(&self, offset: i64) -> Result<usize, Error> {
+        self.address(self.checked(offset.checked_add(self.relative_base))?)
+    }
+
+    fn address(&self, address: i64) -> Result<usize, Error> {
+        if address < 0 {
+            Err(self.error(ErrorKind::NegativeAddress(address)))
+        } else {
+            Ok(address as usize)
         }
+    }
 
-        self.opcodes[dest] = val;
+    fn arg(&self, argi: usize) -> Result<i64, Error> {
+        let val = self.read(self.i + argi);
+        match self.mode(argi)? {
+            Mode::Position => Ok(self.read(self.address(val)?)),
+            Mode::Immediate => Ok(val),
+            Mode::Relative => Ok(self.read(self.relative(val)?)),
+        }
+    }
+
+    fn dest(&self, argi: usize) -> Result<usize, Error> {
+        let val = self.read(self.i + argi);
+        match self.mode(argi)? {
+            Mode::Position => self.address(val),
+            Mode::Relative => self.relative(val),
+            mode => Err(self.error(ErrorKind::InvalidDestMode {
+                argi,
+                mode: mode.digit(),
+            })),
+        }
+    }
+
+    fn set(&mut self, argi: usize, val: i64) -> Result<(), Error> {
+        self.store(self.dest(argi)?, val)
+    }
+
+    /// Decodes the instruction at `address`, if it is valid.
+    fn decode(&self, address: usize) -> Option<Decoded> {
+        let raw = self.read(address);
+        let instruction = self.dialect.decode(raw)?;
+        let mut operands = [Operand {
+            mode: Mode::Position,
+            value: 0,
+        }; 3];
+
+        for argi in 1..=instruction.arity {
+            let mode = self.dialect.mode(raw, argi).ok()?;
+            if instruction.dest == Some(argi) && mode == Mode::Immediate {
+                return None;
+            }
+
+            operands[argi - 1] = Operand {
+                mode,
+                value: self.read(address + argi),
+            };
+        }
+
+        Some(Decoded {
+            instruction,
+            operands,
+        })
+    }
+
+    fn load(&self, operand: Operand) -> Result<i64, Error> {
+        match operand.mode {
+            Mode::Position => Ok(self.read(self.address(operand.value)?)),
+            Mode::Immediate => Ok(operand.value),
+            Mode::Relative => Ok(self.read(self.relative(operand.value)?)),
+        }
+    }
+
+    /// The address written by a decoded operand, which is never immediate.
+    fn operand_dest(&self, operand: Operand) -> Result<usize, Error> {
+        match operand.mode {
+            Mode::Relative => self.relative(operand.value),
+            _ => self.address(operand.value),
+        }
+    }
+
+    /// Stores a function of two decoded operands in a third.
+    fn operate(
+        &mut self,
+        a: Operand,
+        b: Operand,
+        c: Operand,
+        operator: fn(i64, i64) -> Option<i64>,
+    ) -> Result<(), Error> {
+        let val = self.checked(operator(self.load(a)?, self.load(b)?))?;
+        self.store(self.operand_dest(c)?, val)
+    }
+
+    fn store(&mut self, dest: usize, val: i64) -> Result<(), Error> {
+        if let Some(limit) = self.limits.memory {
+            if self.memory.allocates(dest) && self.memory.allocated() + PAGE_SIZE > limit {
+                return Err(self.error(ErrorKind::MemoryLimitExceeded(dest)));
+            }
+        }
+
+        self.write(dest, val);
+
+        if let Some(event) = self.traced() {
+            event.writes.push((dest, val));
+        }
 
         Ok(())
     }
+
+    /// Records the instruction about to execute, with its arguments resolved.
+    fn trace_event(&self) -> Event {
+        let opcode = self.read(self.i);
+        let args = self
+            .dialect
+            .decode(opcode)
+            .map(|instruction| {
+                (1..=instruction.arity)
+                    .map(|argi| {
+                        if instruction.dest == Some(argi) {
+                            self.dest(argi).ok().map(|dest| dest as i64)
+                        } else {
+                            self.arg(argi).ok()
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Event {
+            address: self.i,
+            opcode,
+            args,
+            writes: Vec::new(),
+            input: None,
+            output: None,
+        }
+    }
+
+    fn traced(&mut self) -> Option<&mut Event> {
+        self.trace.as_mut().and_then(|trace| trace.last_mut())
+    }
+}
+
+/// An instruction decoded by `Engine::Decoded`, unused operands are zero.
+#[derive(Copy, Clone)]
+struct Decoded {
+    instruction: &'static Instruction,
+    operands: [Operand; 3],
+}
+
+// Memory
+
+const PAGE_SIZE: usize = 1024;
+
+/// Memory with a flat program image, and pages allocated on demand for writes beyond it. Both
+/// are shared between clones until written to.
+#[derive(Clone)]
+struct Memory {
+    image: Arc<Vec<i64>>,
+    pages: BTreeMap<usize, Arc<[i64; PAGE_SIZE]>>,
+}
+
+impl Memory {
+    fn new(image: &[i64]) -> Memory {
+        Memory {
+            image: Arc::new(image.to_vec()),
+            pages: BTreeMap::new(),
+        }
+    }
+
+    fn get(&self, address: usize) -> i64 {
+        match self.image.get(address) {
+            Some(val) => *val,
+            None => self
+                .pages
+                .get(&(address / PAGE_SIZE))
+                .map(|page| page[address % PAGE_SIZE])
+                .unwrap_or(0),
+        }
+    }
+
+    fn set(&mut self, address: usize, val: i64) {
+        if address < self.image.len() {
+            Arc::make_mut(&mut self.image)[address] = val;
+        } else if val != 0 || !self.allocates(address) {
+            let page = self
+                .pages
+                .entry(address / PAGE_SIZE)
+                .or_insert_with(|| Arc::new([0; PAGE_SIZE]));
+            Arc::make_mut(page)[address % PAGE_SIZE] = val;
+        }
+    }
+
+    /// Whether writing to `address` would allocate a new page.
+    fn allocates(&self, address: usize) -> bool {
+        address >= self.image.len() && !self.pages.contains_key(&(address / PAGE_SIZE))
+    }
+
+    fn allocated(&self) -> usize {
+        self.image.len() + self.pages.len() * PAGE_SIZE
+    }
+
+    /// One past the highest allocated address.
+    fn len(&self) -> usize {
+        self.pages
+            .keys()
+            .next_back()
+            .map(|page| (page + 1) * PAGE_SIZE)
+            .unwrap_or(0)
+            .max(self.image.len())
+    }
+
+    /// The allocated cells beyond the image, by the address of their page.
+    fn pages(&self) -> impl Iterator<Item = (usize, &[i64])> {
+        self.pages
+            .iter()
+            .map(|(page, cells)| (page * PAGE_SIZE, &cells[..]))
+    }
+
+    /// Every allocated cell with its address. Cells of pages that overlap the image are skipped.
+    fn cells(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        let image = self.image.iter().copied().enumerate();
+        let pages = self.pages().flat_map(|(base, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .map(move |(offset, val)| (base + offset, *val))
+        });
+
+        image.chain(pages.filter(move |(address, _)| *address >= self.image.len()))
+    }
+}
+
+/// Hashes a memory cell for a fingerprint, zero cells hash to zero so that unallocated memory
+/// does not change the fingerprint.
+fn cell_hash(address: usize, val: i64) -> u64 {
+    if val == 0 {
+        return 0;
+    }
+
+    // splitmix64
+    let mut hash = (address as u64)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .wrapping_add(val as u64);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+// Disassembly
+
+/// A decoded argument of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb-{}]", -self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+/// A single line of a disassembled program.
+#[derive(Clone, Debug)]
+pub enum Disassembled {
+    Instruction {
+        instruction: &'static Instruction,
+        operands: Vec<Operand>,
+    },
+    Data(i64),
+}
+
+impl Disassembled {
+    /// Decodes the instruction at `address`, falling back to data if it is not valid.
+    pub fn decode(opcodes: &[i64], address: usize, dialect: &Dialect) -> Disassembled {
+        let raw = opcodes[address];
+
+        let line = dialect.decode(raw).and_then(|instruction| {
+            let operands = opcodes.get(address + 1..address + 1 + instruction.arity)?;
+
+            let operands = operands
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    dialect.mode(raw, i + 1).ok().map(|mode| Operand {
+                        mode,
+                        value: *value,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            match instruction.dest {
+                Some(dest) if operands[dest - 1].mode == Mode::Immediate => None,
+                _ => Some(Disassembled::Instruction {
+                    instruction,
+                    operands,
+                }),
+            }
+        });
+
+        line.unwrap_or(Disassembled::Data(raw))
+    }
+
+    /// The number of cells this line occupies.
+    pub fn len(&self) -> usize {
+        match self {
+            Disassembled::Instruction { operands, .. } => operands.len() + 1,
+            Disassembled::Data(_) => 1,
+        }
+    }
+}
+
+impl Display for Disassembled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Disassembled::Instruction {
+                instruction,
+                operands,
+            } => {
+                write!(f, "{}", instruction.mnemonic.to_uppercase())?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Disassembled::Data(value) => write!(f, "DATA {}", value),
+        }
+    }
+}
+
+/// Disassembles a program, returning each line with its address.
+pub fn disassemble(opcodes: &[i64], dialect: &Dialect) -> Vec<(usize, Disassembled)> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address < opcodes.len() {
+        let line = Disassembled::decode(opcodes, address, dialect);
+        let len = line.len();
+        lines.push((address, line));
+        address += len;
+    }
+
+    lines
+}
+
+// Snapshots
+
+/// The full state of a computer, along with any input it has not yet consumed.
+pub struct Snapshot {
+    pub computer: Computer,
+    pub input: Vec<i64>,
+}
+
+const SNAPSHOT_HEADER: &str = "intcode-snapshot 1";
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", SNAPSHOT_HEADER)?;
+        writeln!(f, "ip {}", self.computer.i)?;
+        writeln!(f, "relative_base {}", self.computer.relative_base)?;
+        writeln!(f, "input {}", self.input.iter().join(","))?;
+        writeln!(f, "memory {}", self.computer.memory.image.iter().join(","))?;
+
+        for (base, cells) in self.computer.memory.pages() {
+            writeln!(f, "page {} {}", base, cells.iter().join(","))?;
+        }
+
+        writeln!(f, "dialect {}", self.computer.dialect.name)?;
+
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = failure::Error;
+
+    fn from_str(input: &str) -> Result<Snapshot, failure::Error> {
+        let mut lines = input.lines();
+
+        if lines.next() != Some(SNAPSHOT_HEADER) {
+            return Err(failure::err_msg("not a snapshot"));
+        }
+
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.strip_prefix(' '))
+                .ok_or_else(|| failure::err_msg(format!("missing {}", name)))
+        };
+
+        let i = field("ip")?.parse()?;
+        let relative_base = field("relative_base")?.parse()?;
+        let input = match field("input")? {
+            "" => Vec::new(),
+            input => Computer::parse(input)?,
+        };
+        let mut computer = Computer::new(&Computer::parse(field("memory")?)?);
+        computer.i = i;
+        computer.relative_base = relative_base;
+
+        for line in lines {
+            let mut split = line.splitn(3, ' ');
+            match (split.next(), split.next(), split.next()) {
+                (Some("dialect"), Some(name), None) => computer.dialect = Dialect::named(name)?,
+                (Some("page"), Some(base), Some(cells)) => {
+                    // a page may overlap the end of the image, whose cells were already loaded
+                    let base: usize = base.parse()?;
+                    let image = computer.memory.image.len();
+                    for (offset, val) in Computer::parse(cells)?.into_iter().enumerate() {
+                        if base + offset >= image {
+                            computer.memory.set(base + offset, val);
+                        }
+                    }
+                }
+                _ => return Err(failure::err_msg(format!("invalid line `{}`", line))),
+            }
+        }
+
+        Ok(Snapshot { computer, input })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::empty;
+
+    const LARGE_COMPARISON: [i64; 47] = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+
+    const QUINE: [i64; 16] = [
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+
+    /// Runs a program to completion on one engine, returning its outputs, or the error it failed
+    /// with, and its final memory.
+    fn execute(
+        engine: Engine,
+        program: &[i64],
+        input: &[i64],
+        limits: Limits,
+    ) -> (Result<Vec<i64>, String>, Vec<i64>) {
+        let mut computer = Computer::new(program);
+        computer.set_limits(limits);
+        computer.set_engine(engine);
+
+        let mut queue = Queue::default();
+        queue.input.extend(input);
+
+        let result = match computer.run_io(&mut queue) {
+            Ok(Terminated) => Ok(queue.output.into_iter().collect()),
+            Ok(_) => Err("needs input".to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        (result, computer.memory(0..program.len()))
+    }
+
+    /// Runs a program on every engine, checking that they agree, and returns the result.
+    fn run_limited(
+        program: &[i64],
+        input: &[i64],
+        limits: Limits,
+    ) -> (Result<Vec<i64>, String>, Vec<i64>) {
+        let expected = execute(Engine::Interpreter, program, input, limits);
+
+        for engine in Engine::ALL.iter().copied() {
+            assert_eq!(
+                execute(engine, program, input, limits),
+                expected,
+                "{:?} disagrees with the interpreter",
+                engine
+            );
+        }
+
+        expected
+    }
+
+    fn outputs(program: &[i64], input: &[i64]) -> Vec<i64> {
+        run_limited(program, input, Limits::default()).0.unwrap()
+    }
+
+    fn memory(program: &[i64]) -> Vec<i64> {
+        let (result, memory) = run_limited(program, &[], Limits::default());
+        result.unwrap();
+        memory
+    }
+
+    fn error(program: &[i64], limits: Limits) -> String {
+        run_limited(program, &[], limits).0.unwrap_err()
+    }
+
+    // Opcodes and modes
+
+    #[test]
+    fn add_and_multiply() {
+        assert_eq!(
+            memory(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+            [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]
+        );
+        assert_eq!(memory(&[1, 0, 0, 0, 99]), [2, 0, 0, 0, 99]);
+        assert_eq!(memory(&[2, 3, 0, 3, 99]), [2, 3, 0, 6, 99]);
+        assert_eq!(memory(&[2, 4, 4, 5, 99, 0]), [2, 4, 4, 5, 99, 9801]);
+        assert_eq!(
+            memory(&[1, 1, 1, 4, 99, 5, 6, 0, 99]),
+            [30, 1, 1, 4, 2, 5, 6, 0, 99]
+        );
+    }
+
+    #[test]
+    fn immediate_mode() {
+        assert_eq!(memory(&[1002, 4, 3, 4, 33]), [1002, 4, 3, 4, 99]);
+        assert_eq!(memory(&[1101, 100, -1, 4, 0]), [1101, 100, -1, 4, 99]);
+    }
+
+    #[test]
+    fn input_and_output() {
+        assert_eq!(outputs(&[3, 0, 4, 0, 99], &[42]), [42]);
+        assert_eq!(outputs(&[104, -7, 99], &[]), [-7]);
+    }
+
+    #[test]
+    fn relative_mode() {
+        // reads relative to a base adjusted in every mode
+        assert_eq!(outputs(&[109, 8, 209, -1, 204, -2, 99, 2, 42], &[]), [42]);
+        // writes relative to the base with each instruction that writes
+        assert_eq!(outputs(&[109, 10, 203, 0, 204, 0, 99], &[42]), [42]);
+        assert_eq!(outputs(&[109, 20, 21101, 2, 3, -1, 204, -1, 99], &[]), [5]);
+        assert_eq!(outputs(&[109, 20, 21108, 4, 4, 0, 204, 0, 99], &[]), [1]);
+    }
+
+    #[test]
+    fn memory_beyond_the_program() {
+        assert_eq!(outputs(&[4, 1000, 99], &[]), [0]);
+        assert_eq!(outputs(&[1101, 3, 4, 1000, 4, 1000, 99], &[]), [7]);
+        assert_eq!(
+            outputs(&[109, 1_000_000_000_000, 21101, 7, 0, 5, 204, 5, 99], &[]),
+            [7]
+        );
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // the instruction at 8 is compiled along with the one before it, and overwritten by a halt
+        assert_eq!(
+            memory(&[1101, 0, 99, 8, 1101, 1, 2, 15, 1101, 7, 7, 15, 4, 15, 99, 0]),
+            [1101, 0, 99, 8, 1101, 1, 2, 15, 99, 7, 7, 15, 4, 15, 99, 3]
+        );
+    }
+
+    // Day 5 comparisons and jumps
+
+    #[test]
+    fn equal_to_8() {
+        for program in [
+            [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8],
+            [3, 3, 1108, -1, 8, 3, 4, 3, 99, 0, 0],
+        ]
+        .iter()
+        {
+            assert_eq!(outputs(program, &[8]), [1]);
+            assert_eq!(outputs(program, &[7]), [0]);
+            assert_eq!(outputs(program, &[9]), [0]);
+        }
+    }
+
+    #[test]
+    fn less_than_8() {
+        for program in [
+            [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8],
+            [3, 3, 1107, -1, 8, 3, 4, 3, 99, 0, 0],
+        ]
+        .iter()
+        {
+            assert_eq!(outputs(program, &[7]), [1]);
+            assert_eq!(outputs(program, &[8]), [0]);
+            assert_eq!(outputs(program, &[-100]), [1]);
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        let position = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let immediate = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1, 0, 0, 0];
+
+        for program in [position, immediate].iter() {
+            assert_eq!(outputs(program, &[0]), [0]);
+            assert_eq!(outputs(program, &[5]), [1]);
+        }
+    }
+
+    #[test]
+    fn large_comparison() {
+        assert_eq!(outputs(&LARGE_COMPARISON, &[7]), [999]);
+        assert_eq!(outputs(&LARGE_COMPARISON, &[8]), [1000]);
+        assert_eq!(outputs(&LARGE_COMPARISON, &[9]), [1001]);
+    }
+
+    // Day 9
+
+    #[test]
+    fn quine() {
+        assert_eq!(outputs(&QUINE, &[]), QUINE);
+    }
+
+    #[test]
+    fn large_numbers() {
+        assert_eq!(
+            outputs(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]),
+            [1219070632396864]
+        );
+        assert_eq!(
+            outputs(&[104, 1125899906842624, 99], &[]),
+            [1125899906842624]
+        );
+    }
+
+    // Puzzle programs
+
+    #[test]
+    fn puzzle_programs_agree_on_every_engine() {
+        let day_2 = Computer::parse(include_str!("../input/2")).unwrap();
+        let day_9 = Computer::parse(include_str!("../input/9")).unwrap();
+
+        // day 5's input is not checked in, so its larger example stands in for it
+        assert_eq!(outputs(&LARGE_COMPARISON, &[8]), [1000]);
+
+        let mut patched = day_2;
+        patched[1] = 12;
+        patched[2] = 2;
+        assert_eq!(memory(&patched)[0], 4023471);
+
+        assert_eq!(outputs(&day_9, &[1]), [3546494377]);
+        assert_eq!(outputs(&day_9, &[2]), [47253]);
+
+        // and the compiled engine is not replaced by another when there are no limits
+        let mut computer = Computer::new(&day_9);
+        computer.set_engine(Engine::Compiled);
+        assert_eq!(computer.engine(), Engine::Compiled);
+        computer.set_limits(Limits {
+            detect_loops: true,
+            ..Limits::default()
+        });
+        assert_eq!(computer.engine(), Engine::Decoded);
+    }
+
+    // Capturing output
+
+    #[test]
+    fn run_variants_capture_output() {
+        for engine in Engine::ALL.iter().copied() {
+            let computer = || {
+                let mut computer = Computer::new(&LARGE_COMPARISON);
+                computer.set_engine(engine);
+                computer
+            };
+
+            assert_eq!(
+                computer()
+                    .run_collect(&mut [8].iter().copied().map(Ok))
+                    .unwrap(),
+                [1000]
+            );
+
+            let mut sunk = Vec::new();
+            let result = computer().run_sink(&mut [9].iter().copied().map(Ok), |output| {
+                sunk.push(output);
+                Ok(())
+            });
+            assert_eq!((result.unwrap(), sunk), (3, vec![1001]));
+
+            let result = computer().run_sink(&mut [7].iter().copied().map(Ok), |_| {
+                Err(failure::err_msg("closed"))
+            });
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "output failed: closed at 33 (1105)"
+            );
+        }
+    }
+
+    #[test]
+    fn run_take_stops_after_outputs() {
+        for engine in Engine::ALL.iter().copied() {
+            let mut computer = Computer::new(&QUINE);
+            computer.set_engine(engine);
+
+            assert_eq!(computer.run_take(&mut empty(), 3).unwrap(), QUINE[..3]);
+            assert_eq!(computer.run_take(&mut empty(), 2).unwrap(), QUINE[3..5]);
+            assert_eq!(computer.run_collect(&mut empty()).unwrap(), QUINE[5..]);
+            assert_eq!(computer.run_take(&mut empty(), 1).unwrap(), []);
+        }
+    }
+
+    // Errors and limits
+
+    #[test]
+    fn errors() {
+        let unlimited = Limits::default();
+
+        assert_eq!(error(&[98], unlimited), "invalid opcode at 0 (98)");
+        assert_eq!(
+            error(&[301, 0, 0, 0, 99], unlimited),
+            "invalid mode 3 for argument 1 at 0 (301)"
+        );
+        assert_eq!(
+            error(&[11101, 1, 1, 0, 99], unlimited),
+            "invalid dest mode 1 for argument 3 at 0 (11101)"
+        );
+        assert_eq!(
+            error(&[4, -1, 99], unlimited),
+            "negative address -1 at 0 (4)"
+        );
+        assert_eq!(
+            error(&[1105, 1, 100], unlimited),
+            "jump to 100 out of range at 0 (1105)"
+        );
+        assert_eq!(
+            error(&[1102, i64::MAX, 2, 0, 99], unlimited),
+            "arithmetic overflow at 0 (1102)"
+        );
+        assert_eq!(
+            run_limited(&[3, 0, 99], &[], unlimited).0,
+            Err("needs input".to_string())
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let unlimited = Limits::default();
+
+        assert_eq!(memory(&[1101, i64::MAX - 1, 1, 0, 99])[0], i64::MAX);
+        assert_eq!(
+            error(&[1101, i64::MAX, 1, 0, 99], unlimited),
+            "arithmetic overflow at 0 (1101)"
+        );
+        assert_eq!(
+            error(&[1102, i64::MIN, -1, 0, 99], unlimited),
+            "arithmetic overflow at 0 (1102)"
+        );
+        assert_eq!(
+            error(&[109, i64::MAX, 109, 1, 99], unlimited),
+            "arithmetic overflow at 2 (109)"
+        );
+        assert_eq!(
+            error(&[109, i64::MAX, 204, 1, 99], unlimited),
+            "arithmetic overflow at 2 (204)"
+        );
+    }
+
+    #[test]
+    fn errors_can_be_matched() {
+        fn kind(program: &[i64], limits: Limits) -> (usize, i64, ErrorKind) {
+            let mut computer = Computer::new(program);
+            computer.set_limits(limits);
+            let err = computer.run(&mut empty()).unwrap_err();
+            (err.ip, err.instruction, err.kind)
+        }
+
+        let unlimited = Limits::default();
+        assert!(matches!(
+            kind(&[1101, 1, 1, 5, 98, 0], unlimited),
+            (4, 98, ErrorKind::InvalidOpcode)
+        ));
+        assert!(matches!(
+            kind(&[301, 0, 0, 0, 99], unlimited),
+            (0, 301, ErrorKind::InvalidMode { argi: 1, mode: 3 })
+        ));
+        assert!(matches!(
+            kind(&[11101, 1, 1, 0, 99], unlimited),
+            (0, 11101, ErrorKind::InvalidDestMode { argi: 3, mode: 1 })
+        ));
+        assert!(matches!(
+            kind(&[4, -1, 99], unlimited),
+            (0, 4, ErrorKind::NegativeAddress(-1))
+        ));
+        assert!(matches!(
+            kind(&[1105, 1, 100], unlimited),
+            (0, 1105, ErrorKind::JumpOutOfRange(100))
+        ));
+        assert!(matches!(
+            kind(&[3, 0, 99], unlimited),
+            (0, 3, ErrorKind::InputExhausted)
+        ));
+
+        let steps = Limits {
+            steps: Some(3),
+            ..Limits::default()
+        };
+        assert!(matches!(
+            kind(&[1105, 1, 0], steps),
+            (0, 1105, ErrorKind::StepLimitExceeded(3))
+        ));
+    }
+
+    #[test]
+    fn limits() {
+        let program = [1101, 0, 0, 10, 1105, 1, 0];
+
+        let steps = Limits {
+            steps: Some(10),
+            ..Limits::default()
+        };
+        assert_eq!(error(&program, steps), "exceeded 10 steps at 0 (1101)");
+
+        let loops = Limits {
+            detect_loops: true,
+            ..Limits::default()
+        };
+        assert_eq!(error(&program, loops), "infinite loop at 0 (1101)");
+
+        let memory = Limits {
+            memory: Some(10),
+            ..Limits::default()
+        };
+        assert_eq!(
+            error(&[1101, 1, 1, 5000, 99], memory),
+            "write to 5000 exceeded memory limit at 0 (1101)"
+        );
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        // the image ends partway through the first page, which also holds a written cell
+        let mut program = vec![0; 1500];
+        program[..5].copy_from_slice(&[1101, 7, 0, 2000, 99]);
+        program[1200] = 5;
+
+        let mut computer = Computer::new(&program);
+        computer.run(&mut empty()).unwrap();
+
+        let snapshot = Snapshot {
+            computer,
+            input: vec![1, 2],
+        };
+        let loaded: Snapshot = snapshot.to_string().parse().unwrap();
+
+        assert_eq!(loaded.input, [1, 2]);
+        assert_eq!(loaded.computer.ip(), 4);
+        assert_eq!(loaded.computer.read(1200), 5);
+        assert_eq!(loaded.computer.read(2000), 7);
+        assert_eq!(
+            loaded.computer.memory(0..2048),
+            snapshot.computer.memory(0..2048)
+        );
+        assert_eq!(loaded.to_string(), snapshot.to_string());
+    }
+
+    // Dialects
+
+    fn run_dialect(dialect: &'static Dialect, program: &[i64]) -> Vec<Result<Vec<i64>, String>> {
+        Engine::ALL
+            .iter()
+            .map(|engine| {
+                let mut computer = Computer::new_dialect(program, dialect);
+                computer.set_engine(*engine);
+
+                let mut queue = Queue::default();
+                match computer.run_io(&mut queue) {
+                    Ok(_) => Ok(queue.output.into_iter().collect()),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strict_dialects() {
+        let results = run_dialect(&Dialect::DAY_2, &[1, 0, 0, 0, 4, 0, 99]);
+        assert!(results
+            .iter()
+            .all(|result| result == &Err("invalid opcode at 4 (4)".to_string())));
+
+        let results = run_dialect(&Dialect::DAY_2, &[1001, 0, 0, 0, 99]);
+        assert!(results
+            .iter()
+            .all(|result| result == &Err("invalid mode 1 for argument 2 at 0 (1001)".to_string())));
+
+        let results = run_dialect(&Dialect::DAY_5, &[109, 1, 99]);
+        assert!(results
+            .iter()
+            .all(|result| result == &Err("invalid opcode at 0 (109)".to_string())));
+
+        let results = run_dialect(&Dialect::DAY_5, &[1101, 2, 3, 7, 4, 7, 99, 0]);
+        assert!(results.iter().all(|result| result == &Ok(vec![5])));
+    }
+
+    #[test]
+    fn extended_dialect() {
+        let program = [
+            1110, 12, 10, 20, 1111, 12, 10, 21, 1112, 12, 10, 22, 4, 20, 4, 21, 4, 22, 99, 0, 0, 0,
+            0,
+        ];
+        let results = run_dialect(&Dialect::EXTENDED, &program);
+        assert!(results.iter().all(|result| result == &Ok(vec![8, 14, 6])));
+
+        let results = run_dialect(&Dialect::FULL, &program);
+        assert!(results
+            .iter()
+            .all(|result| result == &Err("invalid opcode at 0 (1110)".to_string())));
+    }
+
+    #[test]
+    fn disassembly_follows_the_dialect() {
+        let program = [1110, 12, 10, 11, 99];
+        let lines = |dialect| -> Vec<_> {
+            disassemble(&program, dialect)
+                .iter()
+                .map(|(_, line)| line.to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            lines(&Dialect::FULL),
+            ["DATA 1110", "DATA 12", "DATA 10", "DATA 11", "HLT"]
+        );
+        assert_eq!(lines(&Dialect::EXTENDED), ["AND #12, #10, [11]", "HLT"]);
+    }
 }
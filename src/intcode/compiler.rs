@@ -0,0 +1,170 @@
+use super::{Computer, Engine, Error, ErrorKind, Handler, Mode, Opcode, Operand};
+
+// Types
+
+type Load = Box<dyn Fn(&Computer) -> Result<i64, Error> + Send + Sync>;
+type Dest = Box<dyn Fn(&Computer) -> Result<usize, Error> + Send + Sync>;
+type Op = Box<dyn Fn(&mut Computer) -> Result<(), Error> + Send + Sync>;
+/// Returns the target of a jump if it is taken.
+type Branch = Box<dyn Fn(&Computer) -> Result<Option<i64>, Error> + Send + Sync>;
+
+/// A run of instructions without control flow or I/O, compiled to closures.
+pub struct Block {
+    /// Each operation with the address of its instruction, and of the one after it.
+    ops: Vec<(usize, usize, Op)>,
+    exit: Exit,
+}
+
+/// How a block ends.
+enum Exit {
+    /// A conditional jump at an address, which was compiled.
+    Branch(usize, Branch),
+    /// An instruction that must be stepped, such as I/O, halting or an invalid instruction.
+    Step(usize),
+}
+
+// Compiling
+
+fn load(operand: Operand) -> Load {
+    let Operand { mode, value } = operand;
+
+    match mode {
+        Mode::Immediate => Box::new(move |_| Ok(value)),
+        Mode::Position if value < 0 => {
+            Box::new(move |computer| Err(computer.error(ErrorKind::NegativeAddress(value))))
+        }
+        Mode::Position => Box::new(move |computer| Ok(computer.read(value as usize))),
+        Mode::Relative => Box::new(move |computer| Ok(computer.read(computer.relative(value)?))),
+    }
+}
+
+fn dest(operand: Operand) -> Dest {
+    let value = operand.value;
+
+    match operand.mode {
+        Mode::Relative => Box::new(move |computer| computer.relative(value)),
+        _ => Box::new(move |computer| computer.address(value)),
+    }
+}
+
+fn operator<F>(operands: [Operand; 3], operator: F) -> Op
+where
+    F: Fn(i64, i64) -> Option<i64> + Send + Sync + 'static,
+{
+    let (a, b, c) = (load(operands[0]), load(operands[1]), dest(operands[2]));
+
+    Box::new(move |computer| {
+        let val = computer.checked(operator(a(computer)?, b(computer)?))?;
+        let dest = c(computer)?;
+        computer.store(dest, val)
+    })
+}
+
+fn branch(operands: [Operand; 3], taken: bool) -> Branch {
+    let (a, b) = (load(operands[0]), load(operands[1]));
+
+    Box::new(move |computer| {
+        if (a(computer)? != 0) == taken {
+            b(computer).map(Some)
+        } else {
+            Ok(None)
+        }
+    })
+}
+
+/// Compiles the instructions starting at `address`, marking the cells they were compiled from as
+/// code. Only instructions within the program image are compiled.
+pub fn compile(computer: &mut Computer, mut address: usize) -> Block {
+    let mut ops = Vec::new();
+
+    let exit = loop {
+        let decoded = match computer.decode(address) {
+            Some(decoded) if address + decoded.instruction.arity < computer.code.len() => decoded,
+            _ => break Exit::Step(address),
+        };
+        let operands = decoded.operands;
+
+        let op: Op = match decoded.instruction.handler {
+            Handler::Builtin(Opcode::Add) => operator(operands, i64::checked_add),
+            Handler::Builtin(Opcode::Mul) => operator(operands, i64::checked_mul),
+            Handler::Builtin(Opcode::Lt) => operator(operands, |a, b| Some((a < b) as i64)),
+            Handler::Builtin(Opcode::Eq) => operator(operands, |a, b| Some((a == b) as i64)),
+            Handler::Operator(function) => operator(operands, function),
+            Handler::Builtin(Opcode::Arb) => {
+                let a = load(operands[0]);
+                Box::new(move |computer| {
+                    let offset = a(computer)?;
+                    computer.adjust_relative_base(offset)
+                })
+            }
+            Handler::Builtin(Opcode::Jnz) | Handler::Builtin(Opcode::Jz) => {
+                let taken = decoded.instruction.is(Opcode::Jnz);
+                break Exit::Branch(address, branch(operands, taken));
+            }
+            Handler::Builtin(Opcode::In)
+            | Handler::Builtin(Opcode::Out)
+            | Handler::Builtin(Opcode::Hlt) => break Exit::Step(address),
+        };
+
+        for cell in address..=address + decoded.instruction.arity {
+            computer.code[cell] = true;
+        }
+
+        let next = address + decoded.instruction.arity + 1;
+        ops.push((address, next, op));
+        address = next;
+    };
+
+    if let Exit::Branch(address, _) = exit {
+        for cell in address..address + 3 {
+            computer.code[cell] = true;
+        }
+    }
+
+    Block { ops, exit }
+}
+
+// Running
+
+impl Block {
+    /// The number of instructions in the block.
+    pub fn len(&self) -> usize {
+        match self.exit {
+            Exit::Branch(..) => self.ops.len() + 1,
+            Exit::Step(_) => self.ops.len(),
+        }
+    }
+
+    /// Runs the block, returns false if the next instruction must be stepped.
+    pub fn run(&self, computer: &mut Computer) -> Result<bool, Error> {
+        for (address, next, op) in self.ops.iter() {
+            computer.i = *address;
+            op(computer)?;
+            computer.steps += 1;
+
+            // the block may have overwritten itself
+            if computer.engine != Engine::Compiled {
+                computer.i = *next;
+                return Ok(false);
+            }
+        }
+
+        match &self.exit {
+            Exit::Branch(address, branch) => {
+                computer.i = *address;
+                match branch(computer)? {
+                    Some(target) => {
+                        computer.jump(target)?;
+                    }
+                    None => computer.i = *address + 3,
+                }
+                computer.steps += 1;
+                Ok(true)
+            }
+            Exit::Step(address) => {
+                computer.i = *address;
+                Ok(false)
+            }
+        }
+    }
+}
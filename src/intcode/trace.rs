@@ -0,0 +1,131 @@
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// Types
+
+/// A single executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub address: usize,
+    pub opcode: i64,
+    /// Argument values, with destinations resolved to addresses. `None` for an argument that
+    /// could not be resolved, such as one at a negative address.
+    pub args: Vec<Option<i64>>,
+    pub writes: Vec<(usize, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+// Formatting
+
+/// Formats as `address opcode args [wADDR=VAL].. [iVAL] [oVAL]`, one event per line. Arguments
+/// that could not be resolved are written as `?`.
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ", self.address, self.opcode)?;
+
+        if self.args.is_empty() {
+            write!(f, "-")?;
+        } else {
+            let args = self.args.iter().map(|arg| match arg {
+                Some(arg) => arg.to_string(),
+                None => "?".to_string(),
+            });
+            write!(f, "{}", args.format(","))?;
+        }
+
+        for (address, value) in self.writes.iter() {
+            write!(f, " w{}={}", address, value)?;
+        }
+
+        if let Some(input) = self.input {
+            write!(f, " i{}", input)?;
+        }
+
+        if let Some(output) = self.output {
+            write!(f, " o{}", output)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Event {
+    type Err = failure::Error;
+
+    fn from_str(line: &str) -> Result<Event, failure::Error> {
+        let mut fields = line.split_whitespace();
+        let mut next = || {
+            fields
+                .next()
+                .ok_or_else(|| failure::err_msg(format!("truncated event `{}`", line)))
+        };
+
+        let mut event = Event {
+            address: next()?.parse()?,
+            opcode: next()?.parse()?,
+            args: match next()? {
+                "-" => Vec::new(),
+                args => args
+                    .split(',')
+                    .map(|arg| match arg {
+                        "?" => Ok(None),
+                        arg => arg.parse().map(Some),
+                    })
+                    .collect::<Result<Vec<_>, std::num::ParseIntError>>()?,
+            },
+            writes: Vec::new(),
+            input: None,
+            output: None,
+        };
+
+        for field in fields {
+            let (tag, value) = field.split_at(1);
+            match tag {
+                "w" => {
+                    let (address, value) = value
+                        .split('=')
+                        .collect_tuple()
+                        .ok_or_else(|| failure::err_msg(format!("invalid write `{}`", field)))?;
+                    event.writes.push((address.parse()?, value.parse()?));
+                }
+                "i" => event.input = Some(value.parse()?),
+                "o" => event.output = Some(value.parse()?),
+                _ => return Err(failure::err_msg(format!("invalid field `{}`", field))),
+            }
+        }
+
+        Ok(event)
+    }
+}
+
+pub fn parse(input: &str) -> Result<Vec<Event>, failure::Error> {
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(Event::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip() {
+        let text = "4 1001 7,?,9 w9=12\n6 3 9 w9=5 i5\n8 4 5 o5\n10 99 -\n";
+        let events = parse(text).unwrap();
+
+        assert_eq!(events[0].args, [Some(7), None, Some(9)]);
+        assert_eq!(events[1].input, Some(5));
+        assert_eq!(events[2].output, Some(5));
+        assert_eq!(
+            events.iter().map(|event| format!("{}\n", event)).join(""),
+            text
+        );
+
+        assert!(parse("4 1001").is_err());
+        assert!(parse("4 1001 1,2,3 x1").is_err());
+    }
+}
@@ -1,6 +1,18 @@
+mod adventure;
+mod analysis;
+mod ascii;
+mod asm;
+mod bench;
+mod debugger;
+mod disasm;
+mod fuzz;
+mod grid;
 mod image;
 mod intcode;
+mod network;
+mod patch;
 mod q1;
+mod q11;
 mod q2;
 mod q3;
 mod q4;
@@ -9,6 +21,9 @@ mod q6;
 mod q7;
 mod q8;
 mod q9;
+mod search;
+mod symbolic;
+mod trace;
 
 use structopt::StructOpt;
 
@@ -23,6 +38,19 @@ enum Options {
     Q7(q7::Options),
     Q8(q8::Options),
     Q9(q9::Options),
+    Q11(q11::Options),
+    Disasm(disasm::Options),
+    Asm(asm::Options),
+    Debug(debugger::Options),
+    Trace(trace::Options),
+    Network(network::Options),
+    Bench(bench::Options),
+    Analyze(analysis::Options),
+    Fuzz(fuzz::Options),
+    Ascii(ascii::Options),
+    Adventure(adventure::Options),
+    Symbolic(symbolic::Options),
+    Search(search::Options),
 }
 
 fn main() -> Result<(), failure::Error> {
@@ -36,5 +64,18 @@ fn main() -> Result<(), failure::Error> {
         Options::Q7(options) => q7::run(&options),
         Options::Q8(options) => q8::run(&options),
         Options::Q9(options) => q9::run(&options),
+        Options::Q11(options) => q11::run(&options),
+        Options::Disasm(options) => disasm::run(&options),
+        Options::Asm(options) => asm::run(&options),
+        Options::Debug(options) => debugger::run(&options),
+        Options::Trace(options) => trace::run(&options),
+        Options::Network(options) => network::run(&options),
+        Options::Bench(options) => bench::run(&options),
+        Options::Analyze(options) => analysis::run(&options),
+        Options::Fuzz(options) => fuzz::run(&options),
+        Options::Ascii(options) => ascii::run(&options),
+        Options::Adventure(options) => adventure::run(&options),
+        Options::Symbolic(options) => symbolic::run(&options),
+        Options::Search(options) => search::run(&options),
     }
 }
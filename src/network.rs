@@ -0,0 +1,524 @@
+use crate::intcode::{Computer, Engine, Limits, Queue, Step};
+use crate::patch::Patch;
+use std::io::{stdin, Read};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// Types
+
+/// The final value produced by a network, and the machine that produced it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub machine: usize,
+    pub value: i64,
+}
+
+/// Describes how outputs are routed between the machines of a network.
+pub trait Topology {
+    /// Routes a value output by machine `from`, returns an outcome if the network should stop.
+    fn route(
+        &mut self,
+        from: usize,
+        value: i64,
+        queues: &mut [Queue],
+    ) -> Result<Option<Outcome>, failure::Error>;
+
+    /// The input provided to a machine that is waiting on an empty queue, if any.
+    fn empty_input(&self) -> Option<i64> {
+        None
+    }
+
+    /// Called when every machine is waiting for input that will never arrive. Returns an outcome
+    /// if the network should stop, or injects input to wake it up.
+    fn idle(&mut self, _queues: &mut [Queue]) -> Result<Option<Outcome>, failure::Error> {
+        Err(failure::err_msg("network is deadlocked"))
+    }
+
+    /// The outcome once every machine has terminated.
+    fn finish(&self) -> Option<Outcome>;
+}
+
+/// Each machine feeds the next, the outputs of the last leave the network.
+#[derive(Default)]
+pub struct Chain {
+    last: Option<Outcome>,
+}
+
+/// Each machine feeds the next, and the last feeds the first.
+#[derive(Default)]
+pub struct Ring {
+    last: Option<Outcome>,
+}
+
+/// Machines send `(address, x, y)` packets to each other. Packets addressed to 255 go to the NAT,
+/// which resends the last one to machine 0 when the network is idle, or stop the network if there
+/// is no NAT.
+pub struct Bus {
+    nat: bool,
+    partial: Vec<Vec<i64>>,
+    held: Option<(usize, i64, i64)>,
+    last_sent: Option<i64>,
+}
+
+impl Bus {
+    pub const NAT: i64 = 255;
+
+    pub fn new(nat: bool) -> Bus {
+        Bus {
+            nat,
+            partial: Vec::new(),
+            held: None,
+            last_sent: None,
+        }
+    }
+}
+
+pub struct Network<T: Topology> {
+    computers: Vec<Computer>,
+    queues: Vec<Queue>,
+    terminated: Vec<bool>,
+    /// Whether a machine's queue holds filler input from the topology.
+    filled: Vec<bool>,
+    topology: T,
+}
+
+// Topologies
+
+impl Topology for Chain {
+    fn route(
+        &mut self,
+        from: usize,
+        value: i64,
+        queues: &mut [Queue],
+    ) -> Result<Option<Outcome>, failure::Error> {
+        match queues.get_mut(from + 1) {
+            Some(queue) => queue.input.push_back(value),
+            None => {
+                self.last = Some(Outcome {
+                    machine: from,
+                    value,
+                })
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn finish(&self) -> Option<Outcome> {
+        self.last
+    }
+}
+
+impl Topology for Ring {
+    fn route(
+        &mut self,
+        from: usize,
+        value: i64,
+        queues: &mut [Queue],
+    ) -> Result<Option<Outcome>, failure::Error> {
+        let next = (from + 1) % queues.len();
+
+        if next == 0 {
+            self.last = Some(Outcome {
+                machine: from,
+                value,
+            });
+        }
+
+        queues[next].input.push_back(value);
+
+        Ok(None)
+    }
+
+    fn finish(&self) -> Option<Outcome> {
+        self.last
+    }
+}
+
+impl Topology for Bus {
+    fn route(
+        &mut self,
+        from: usize,
+        value: i64,
+        queues: &mut [Queue],
+    ) -> Result<Option<Outcome>, failure::Error> {
+        if self.partial.len() <= from {
+            self.partial.resize(from + 1, Vec::new());
+        }
+
+        self.partial[from].push(value);
+        if self.partial[from].len() < 3 {
+            return Ok(None);
+        }
+
+        let packet = std::mem::take(&mut self.partial[from]);
+        let (address, x, y) = (packet[0], packet[1], packet[2]);
+
+        if address == Bus::NAT {
+            if self.nat {
+                self.held = Some((from, x, y));
+                Ok(None)
+            } else {
+                Ok(Some(Outcome {
+                    machine: from,
+                    value: y,
+                }))
+            }
+        } else {
+            let queue = queues
+                .get_mut(address as usize)
+                .filter(|_| address >= 0)
+                .ok_or_else(|| failure::err_msg(format!("invalid address {}", address)))?;
+
+            queue.input.push_back(x);
+            queue.input.push_back(y);
+
+            Ok(None)
+        }
+    }
+
+    fn empty_input(&self) -> Option<i64> {
+        Some(-1)
+    }
+
+    fn idle(&mut self, queues: &mut [Queue]) -> Result<Option<Outcome>, failure::Error> {
+        let (machine, x, y) = self
+            .held
+            .ok_or_else(|| failure::err_msg("network is idle with no packet for the NAT"))?;
+
+        if self.last_sent == Some(y) {
+            return Ok(Some(Outcome { machine, value: y }));
+        }
+
+        self.last_sent = Some(y);
+        queues[0].input.push_back(x);
+        queues[0].input.push_back(y);
+
+        Ok(None)
+    }
+
+    fn finish(&self) -> Option<Outcome> {
+        self.held
+            .map(|(machine, _, y)| Outcome { machine, value: y })
+    }
+}
+
+// Scheduling
+
+impl<T: Topology> Network<T> {
+    pub fn new(opcodes: &[i64], machines: usize, topology: T) -> Network<T> {
+        Network {
+            computers: (0..machines).map(|_| Computer::new(opcodes)).collect(),
+            queues: vec![Queue::default(); machines],
+            terminated: vec![false; machines],
+            filled: vec![false; machines],
+            topology,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        for computer in self.computers.iter_mut() {
+            computer.set_limits(limits);
+        }
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        for computer in self.computers.iter_mut() {
+            computer.set_engine(engine);
+        }
+    }
+
+    /// Queues an input for a machine, before the network is run.
+    pub fn input(&mut self, machine: usize, value: i64) {
+        self.queues[machine].input.push_back(value);
+    }
+
+    /// Runs every machine in turn until the topology stops the network, or all have terminated.
+    pub fn run(&mut self) -> Result<Outcome, failure::Error> {
+        let mut idle_rounds = 0;
+
+        while !self.terminated.iter().all(|b| *b) {
+            let mut active = false;
+
+            for i in 0..self.computers.len() {
+                if self.terminated[i] {
+                    continue;
+                }
+
+                // filler input does not count as activity
+                let real = self.queues[i].input.len() > self.filled[i] as usize;
+                self.filled[i] = false;
+
+                match self.computers[i].run_io(&mut self.queues[i])? {
+                    Step::Terminated => self.terminated[i] = true,
+                    _ => {
+                        if let Some(value) = self.topology.empty_input() {
+                            self.queues[i].input.push_back(value);
+                            self.filled[i] = true;
+                        }
+                    }
+                }
+
+                active |= real || !self.queues[i].output.is_empty();
+
+                while let Some(value) = self.queues[i].output.pop_front() {
+                    if let Some(outcome) = self.topology.route(i, value, &mut self.queues)? {
+                        return Ok(outcome);
+                    }
+                }
+            }
+
+            idle_rounds = if active { 0 } else { idle_rounds + 1 };
+
+            if idle_rounds >= 2 && !self.terminated.iter().all(|b| *b) {
+                if let Some(outcome) = self.topology.idle(&mut self.queues)? {
+                    return Ok(outcome);
+                }
+                idle_rounds = 0;
+            }
+        }
+
+        self.topology
+            .finish()
+            .ok_or_else(|| failure::err_msg("no output!"))
+    }
+}
+
+// Main
+
+/// An input for a single machine, as `MACHINE=VALUE`.
+pub struct Input {
+    machine: usize,
+    value: i64,
+}
+
+impl FromStr for Input {
+    type Err = failure::Error;
+
+    fn from_str(input: &str) -> Result<Input, failure::Error> {
+        let mut split = input.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(machine), Some(value)) => Ok(Input {
+                machine: machine.parse()?,
+                value: value.parse()?,
+            }),
+            _ => Err(failure::err_msg(format!(
+                "expected MACHINE=VALUE, found {}",
+                input
+            ))),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    Chain {
+        machines: usize,
+        #[structopt(short, long)]
+        input: Vec<Input>,
+    },
+    Ring {
+        machines: usize,
+        #[structopt(short, long)]
+        input: Vec<Input>,
+    },
+    Bus {
+        machines: usize,
+        #[structopt(long)]
+        nat: bool,
+    },
+}
+
+fn start<T: Topology>(
+    opcodes: &[i64],
+    machines: usize,
+    inputs: &[Input],
+    topology: T,
+) -> Result<Outcome, failure::Error> {
+    let mut network = Network::new(opcodes, machines, topology);
+
+    for input in inputs.iter() {
+        if input.machine >= machines {
+            return Err(failure::err_msg(format!("no machine {}", input.machine)));
+        }
+        network.input(input.machine, input.value);
+    }
+
+    network.run()
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let opcodes = options.patch.apply(Computer::parse(&input)?)?;
+
+    let outcome = match &options.command {
+        Command::Chain { machines, input } => start(&opcodes, *machines, input, Chain::default()),
+        Command::Ring { machines, input } => start(&opcodes, *machines, input, Ring::default()),
+        Command::Bus { machines, nat } => {
+            // each machine is first told its own address
+            let addresses: Vec<_> = (0..*machines)
+                .map(|machine| Input {
+                    machine,
+                    value: machine as i64,
+                })
+                .collect();
+            start(&opcodes, *machines, &addresses, Bus::new(*nat))
+        }
+    }?;
+
+    println!("machine {}: {}", outcome.machine, outcome.value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::intcode::Dialect;
+
+    /// Reads a value, outputs it plus one, and halts.
+    const INCREMENT: &str = "
+            in [x]
+            add [x], #1, [x]
+            out [x]
+            hlt
+        x:  data 0
+    ";
+
+    /// Reads values and outputs each plus one, until it outputs 10 or more.
+    const COUNT: &str = "
+        loop: in [x]
+              add [x], #1, [x]
+              out [x]
+              lt [x], #10, [t]
+              jnz [t], #loop
+              hlt
+        x:    data 0
+        t:    data 0
+    ";
+
+    /// Machine 0 sends (1, 7, 8) then idles, other machines forward every packet to the NAT.
+    const FORWARD: &str = "
+              in [addr]
+              jnz [addr], #recv
+              out #1
+              out #7
+              out #8
+        idle: in [x]
+              jz #0, #idle
+        recv: in [x]
+              eq [x], #-1, [t]
+              jnz [t], #recv
+              in [y]
+              out #255
+              out [x]
+              out [y]
+              jz #0, #recv
+        addr: data 0
+        x:    data 0
+        y:    data 0
+        t:    data 0
+    ";
+
+    fn program(source: &str) -> Vec<i64> {
+        assemble(source, &Dialect::FULL).unwrap()
+    }
+
+    fn bus(source: &str, machines: usize, nat: bool) -> Result<Outcome, failure::Error> {
+        let mut network = Network::new(&program(source), machines, Bus::new(nat));
+        for machine in 0..machines {
+            network.input(machine, machine as i64);
+        }
+        network.run()
+    }
+
+    #[test]
+    fn chain() {
+        let mut network = Network::new(&program(INCREMENT), 3, Chain::default());
+        network.input(0, 10);
+
+        assert_eq!(
+            network.run().unwrap(),
+            Outcome {
+                machine: 2,
+                value: 13
+            }
+        );
+    }
+
+    #[test]
+    fn chain_deadlock() {
+        let mut network = Network::new(&program(INCREMENT), 2, Chain::default());
+
+        assert_eq!(
+            network.run().unwrap_err().to_string(),
+            "network is deadlocked"
+        );
+    }
+
+    #[test]
+    fn ring() {
+        let mut network = Network::new(&program(COUNT), 2, Ring::default());
+        network.input(0, 0);
+
+        // the last machine outputs 10 and halts, the first outputs 11 to a halted machine
+        assert_eq!(
+            network.run().unwrap(),
+            Outcome {
+                machine: 1,
+                value: 10
+            }
+        );
+    }
+
+    #[test]
+    fn bus_without_nat() {
+        assert_eq!(
+            bus(FORWARD, 3, false).unwrap(),
+            Outcome {
+                machine: 1,
+                value: 8
+            }
+        );
+    }
+
+    #[test]
+    fn bus_with_nat() {
+        // the NAT resends (7, 8) to machine 0 when idle, and stops when it repeats itself
+        assert_eq!(
+            bus(FORWARD, 3, true).unwrap(),
+            Outcome {
+                machine: 1,
+                value: 8
+            }
+        );
+
+        assert_eq!(
+            bus("loop: in [x]\njz #0, #loop\nx: data 0\n", 2, true)
+                .unwrap_err()
+                .to_string(),
+            "network is idle with no packet for the NAT"
+        );
+    }
+
+    #[test]
+    fn bus_invalid_address() {
+        assert_eq!(
+            bus("out #5\nout #0\nout #0\nhlt\n", 2, false)
+                .unwrap_err()
+                .to_string(),
+            "invalid address 5"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Memory cells to set before a program runs.
+#[derive(StructOpt)]
+pub struct Patch {
+    /// Sets a memory cell before running, as ADDR=VALUE.
+    #[structopt(long = "set", number_of_values = 1, parse(try_from_str = parse_assignment))]
+    set: Vec<(usize, i64)>,
+    /// A file of ADDR=VALUE lines to apply before any --set, `#` starts a comment.
+    #[structopt(long, parse(from_os_str))]
+    patch: Option<PathBuf>,
+}
+
+fn parse_assignment(text: &str) -> Result<(usize, i64), failure::Error> {
+    let mut split = text.splitn(2, '=');
+    match (split.next(), split.next()) {
+        (Some(address), Some(value)) => Ok((address.trim().parse()?, value.trim().parse()?)),
+        _ => Err(failure::err_msg(format!(
+            "expected ADDR=VALUE, found `{}`",
+            text
+        ))),
+    }
+}
+
+fn parse_file(text: &str) -> Result<Vec<(usize, i64)>, failure::Error> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, text)| {
+            parse_assignment(text)
+                .map_err(|err| failure::err_msg(format!("line {}: {}", line, err)))
+        })
+        .collect()
+}
+
+impl Patch {
+    /// Sets the patched cells of a program, which must all be within it.
+    pub fn apply(&self, mut opcodes: Vec<i64>) -> Result<Vec<i64>, failure::Error> {
+        let file = match &self.patch {
+            Some(path) => parse_file(&read_to_string(path)?)?,
+            None => Vec::new(),
+        };
+
+        for (address, value) in file.iter().chain(self.set.iter()) {
+            match opcodes.get_mut(*address) {
+                Some(cell) => *cell = *value,
+                None => {
+                    return Err(failure::err_msg(format!(
+                        "address {} is outside the program of {} cells",
+                        address,
+                        opcodes.len()
+                    )))
+                }
+            }
+        }
+
+        Ok(opcodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_patches() {
+        assert_eq!(parse_assignment("0=2").unwrap(), (0, 2));
+        assert_eq!(parse_assignment(" 12 = -7 ").unwrap(), (12, -7));
+        assert!(parse_assignment("12").is_err());
+        assert!(parse_assignment("-1=2").is_err());
+
+        assert_eq!(
+            parse_file("# wake up the robot\n0=2\n\n1=12  # noun\n").unwrap(),
+            [(0, 2), (1, 12)]
+        );
+        assert_eq!(
+            parse_file("0=2\n1:12\n").unwrap_err().to_string(),
+            "line 2: expected ADDR=VALUE, found `1:12`"
+        );
+    }
+
+    #[test]
+    fn patches_must_be_within_the_program() {
+        let patch = Patch {
+            set: vec![(0, 2), (2, 5)],
+            patch: None,
+        };
+
+        assert_eq!(patch.apply(vec![1, 0, 0, 99]).unwrap(), [2, 0, 5, 99]);
+        assert_eq!(
+            patch.apply(vec![99]).unwrap_err().to_string(),
+            "address 2 is outside the program of 1 cells"
+        );
+    }
+}
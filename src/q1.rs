@@ -13,8 +13,8 @@ pub fn run() -> Result<(), failure::Error> {
     let sum = io::stdin()
         .lock()
         .lines()
-        .fold(Ok(0), |total, next| -> Result<i64, failure::Error> {
-            Ok(total? + fuel(next?.parse::<i64>()?))
+        .try_fold(0, |total, next| -> Result<i64, failure::Error> {
+            Ok(total + fuel(next?.parse::<i64>()?))
         })?;
 
     println!("{}", sum);
@@ -0,0 +1,89 @@
+use crate::grid::{Direction, Grid, Point};
+use crate::image::glyph;
+use crate::intcode::{Computer, Engine, Queue, Step};
+use crate::patch::Patch;
+use std::io::{stdin, Read};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    part: Part,
+}
+
+#[derive(StructOpt)]
+pub enum Part {
+    A,
+    B,
+}
+
+const BLACK: i64 = 0;
+const WHITE: i64 = 1;
+
+/// Runs the robot on a hull whose starting panel is `start`, returns the panels it painted.
+fn paint(opcodes: &[i64], start: i64) -> Result<Grid<i64>, failure::Error> {
+    let mut computer = Computer::new(opcodes);
+    computer.set_engine(Engine::Compiled);
+
+    let mut hull = Grid::new();
+    if start != BLACK {
+        hull.insert(Point::ORIGIN, start);
+    }
+
+    let mut position = Point::ORIGIN;
+    let mut direction = Direction::Up;
+    let mut queue = Queue::default();
+
+    loop {
+        // the camera sees black for panels that were never painted
+        let colour = hull.get(position).copied().unwrap_or(BLACK);
+        queue.input.push_back(colour);
+
+        let step = computer.run_io(&mut queue)?;
+
+        while queue.output.len() >= 2 {
+            let colour = queue.output.pop_front().unwrap();
+            let turn = queue.output.pop_front().unwrap();
+
+            if colour != BLACK && colour != WHITE {
+                return Err(failure::err_msg(format!("invalid colour {}", colour)));
+            }
+            hull.insert(position, colour);
+
+            direction = match turn {
+                0 => direction.turn_left(),
+                1 => direction.turn_right(),
+                _ => return Err(failure::err_msg(format!("invalid turn {}", turn))),
+            };
+            position = position + direction;
+        }
+
+        match step {
+            Step::Terminated if queue.output.is_empty() => return Ok(hull),
+            Step::Terminated => return Err(failure::err_msg("colour without a turn")),
+            _ => (),
+        }
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let opcodes = options.patch.apply(Computer::parse(&input)?)?;
+
+    match options.part {
+        Part::A => println!("{}", paint(&opcodes, BLACK)?.len()),
+        Part::B => {
+            let hull = paint(&opcodes, WHITE)?;
+            print!(
+                "{}",
+                hull.render(|colour| glyph(colour.copied().unwrap_or(BLACK) as u8))
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,35 +1,114 @@
-use crate::intcode::Computer;
-use itertools::iproduct;
+use crate::intcode::{Computer, Dialect, Engine, Limits};
+use crate::patch::Patch;
+use crate::search::{Parameter, Search, Slot};
+use crate::symbolic::{Machine, Symbol};
 use std::io::{stdin, Read};
 use std::iter::empty;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-pub enum Options {
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    part: Part,
+}
+
+#[derive(StructOpt)]
+pub enum Part {
     A { noun: i64, verb: i64 },
     B,
 }
 
+/// Candidates that run for longer than this are not a match. Loops are caught by the step limit
+/// rather than by loop detection, which would keep the compiled engine from running.
+const LIMITS: Limits = Limits {
+    steps: Some(100_000),
+    memory: Some(1 << 16),
+    detect_loops: false,
+};
+
+/// The output part B searches for.
+const TARGET: i64 = 19690720;
+
+/// Finds the noun and verb that produce 19690720, as `100 * noun + verb`.
+pub fn search(opcodes: &[i64], engine: Engine) -> Result<i64, failure::Error> {
+    let parameters = [1, 2]
+        .iter()
+        .map(|address| Parameter {
+            slot: Slot::Cell(*address),
+            range: 0..=99,
+        })
+        .collect();
+
+    let mut search = Search::new(opcodes, &Dialect::DAY_2, parameters)?;
+    search.set_limits(LIMITS);
+    search.set_engine(engine);
+
+    match search.first(|run| run.cell0 == TARGET)?.as_deref() {
+        Some([noun, verb]) => Ok(noun * 100 + verb),
+        _ => Err(failure::err_msg("no match!")),
+    }
+}
+
+/// Finds the noun and verb like `search`, but by running the program once with both as symbols
+/// and solving for the verb, which works if the output is linear in them.
+fn solve(opcodes: &[i64]) -> Result<i64, failure::Error> {
+    let mut machine = Machine::new(opcodes, &Dialect::DAY_2, &[1, 2])?;
+    machine.run()?;
+
+    let linear = machine
+        .read(0)
+        .linear()
+        .ok_or_else(|| failure::err_msg("output is not linear in the noun and verb"))?;
+    let (a, b) = (
+        linear.coefficient(Symbol::Cell(1)),
+        linear.coefficient(Symbol::Cell(2)),
+    );
+
+    let (noun, verb) = (0..=99)
+        .filter_map(|noun| {
+            // a noun that overflows the calculation has no verb
+            let rest = TARGET
+                .checked_sub(linear.constant)?
+                .checked_sub(a.checked_mul(noun)?)?;
+            match b {
+                0 if rest == 0 => Some((noun, 0)),
+                0 => None,
+                _ if rest.checked_rem(b)? == 0 && (0..=99).contains(&(rest / b)) => {
+                    Some((noun, rest / b))
+                }
+                _ => None,
+            }
+        })
+        .next()
+        .ok_or_else(|| failure::err_msg("no match!"))?;
+
+    // the solution assumes no reads through the noun and verb affect the output, so check it
+    let output = Computer::new_overrides(opcodes, &Dialect::DAY_2, vec![(1, noun), (2, verb)])
+        .run(&mut empty())?;
+    if output != TARGET {
+        return Err(failure::err_msg(format!(
+            "noun {} and verb {} produce {}",
+            noun, verb, output
+        )));
+    }
+
+    Ok(noun * 100 + verb)
+}
+
 pub fn run(options: &Options) -> Result<(), failure::Error> {
     let mut input = String::new();
     stdin().read_to_string(&mut input)?;
 
-    let opcodes = Computer::parse(&input)?;
+    let opcodes = options.patch.apply(Computer::parse(&input)?)?;
 
-    let output = match options {
-        Options::A { noun, verb } => {
-            Computer::new_overrides(&opcodes, vec![(1, *noun), (2, *verb)]).run(&mut empty())?
+    let output = match &options.part {
+        Part::A { noun, verb } => {
+            Computer::new_overrides(&opcodes, &Dialect::DAY_2, vec![(1, *noun), (2, *verb)])
+                .run(&mut empty())?
         }
-        Options::B => iproduct!((0..99), (0..99))
-            .filter_map(|(a, b)| {
-                match Computer::new_overrides(&opcodes, vec![(1, a), (2, b)]).run(&mut empty()) {
-                    Ok(output) if output == 19690720 => Some(Ok(a * 100 + b)),
-                    Ok(_) => None,
-                    Err(err) => Some(Err(err)),
-                }
-            })
-            .next()
-            .ok_or_else(|| failure::err_msg("no match!"))??,
+        Part::B => solve(&opcodes)?,
     };
 
     println!("{}", output);
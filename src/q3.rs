@@ -1,3 +1,4 @@
+use crate::grid::Point;
 use itertools::iproduct;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
@@ -24,12 +25,6 @@ struct Shift {
     distance: i64,
 }
 
-#[derive(Copy, Clone)]
-struct Point {
-    pub x: i64,
-    pub y: i64,
-}
-
 #[derive(Copy, Clone)]
 struct Line {
     axis: Axis,
@@ -74,32 +69,26 @@ fn shifts_pair(input: &str) -> Result<(Vec<Shift>, Vec<Shift>), failure::Error>
 
 // Solving
 
-fn lines(shifts: &Vec<Shift>) -> Vec<Line> {
+fn lines(shifts: &[Shift]) -> Vec<Line> {
     shifts
         .iter()
-        .scan(Point { x: 0, y: 0 }, |point, shift| {
+        .scan(Point::ORIGIN, |point, shift| {
             let origin = match shift.axis {
                 Axis::X => {
                     let old = point.x;
                     point.x = old + shift.distance;
-                    Point {
-                        x: min(old, point.x),
-                        y: point.y,
-                    }
+                    Point::new(min(old, point.x), point.y)
                 }
                 Axis::Y => {
                     let old = point.y;
                     point.y = old + shift.distance;
-                    Point {
-                        x: point.x,
-                        y: min(old, point.y),
-                    }
+                    Point::new(point.x, min(old, point.y))
                 }
             };
 
             Some(Line {
                 axis: shift.axis,
-                origin: origin,
+                origin,
                 distance: shift.distance.abs(),
             })
         })
@@ -117,10 +106,7 @@ fn intersect(first: &Line, second: &Line) -> Option<Point> {
                 && first.origin.y >= second.origin.y
                 && first.origin.y <= (second.origin.y + second.distance)
             {
-                Some(Point {
-                    x: second.origin.x,
-                    y: first.origin.y,
-                })
+                Some(Point::new(second.origin.x, first.origin.y))
             } else {
                 None
             }
@@ -128,21 +114,21 @@ fn intersect(first: &Line, second: &Line) -> Option<Point> {
     }
 }
 
-fn intersections(first: &Vec<Line>, second: &Vec<Line>) -> Vec<(Point, usize, usize)> {
+fn intersections(first: &[Line], second: &[Line]) -> Vec<(Point, usize, usize)> {
     iproduct!(first.iter().enumerate(), second.iter().enumerate())
         .flat_map(|((i1, line1), (i2, line2))| intersect(line1, line2).map(|point| (point, i1, i2)))
         .collect()
 }
 
-fn minimum_manhattan(intersections: &Vec<(Point, usize, usize)>) -> Option<i64> {
+fn minimum_manhattan(intersections: &[(Point, usize, usize)]) -> Option<i64> {
     intersections
         .iter()
-        .map(|(point, _, _)| (point.x.abs() + point.y.abs()))
+        .map(|(point, _, _)| point.manhattan(Point::ORIGIN))
         .filter(|distance| *distance > 0)
         .min()
 }
 
-fn delay(lines: &Vec<Line>, index: usize, point: Point) -> i64 {
+fn delay(lines: &[Line], index: usize, point: Point) -> i64 {
     let initial_delay: i64 = lines[0..index].iter().map(|line| line.distance.abs()).sum();
 
     let last = &lines[index];
@@ -157,9 +143,9 @@ fn delay(lines: &Vec<Line>, index: usize, point: Point) -> i64 {
 }
 
 fn minimum_delay(
-    first: &Vec<Line>,
-    second: &Vec<Line>,
-    intersections: &Vec<(Point, usize, usize)>,
+    first: &[Line],
+    second: &[Line],
+    intersections: &[(Point, usize, usize)],
 ) -> Option<i64> {
     intersections
         .iter()
@@ -6,19 +6,19 @@ pub enum Options {
     B,
 }
 
-fn pairs(digits: &Vec<u32>) -> impl Iterator<Item = (&u32, &u32)> {
+fn pairs(digits: &[u32]) -> impl Iterator<Item = (&u32, &u32)> {
     digits[0..digits.len() - 1].iter().zip(digits[1..].iter())
 }
 
-fn ascending(digits: &Vec<u32>) -> bool {
+fn ascending(digits: &[u32]) -> bool {
     pairs(digits).all(|(a, b)| a <= b)
 }
 
-fn has_two_adjacent(digits: &Vec<u32>) -> bool {
+fn has_two_adjacent(digits: &[u32]) -> bool {
     pairs(digits).any(|(a, b)| a == b)
 }
 
-fn has_exactly_two_adjacent(digits: &Vec<u32>) -> bool {
+fn has_exactly_two_adjacent(digits: &[u32]) -> bool {
     let mut value = 0;
     let mut length = 0;
 
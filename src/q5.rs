@@ -1,16 +1,133 @@
-use crate::intcode::Computer;
-use std::io::{stdin, BufRead};
+use crate::intcode::{Computer, Step};
+use crate::patch::Patch;
+use itertools::Itertools;
+use std::io::{stdin, Read};
+use std::iter::once;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 pub struct Options {
-    program: String,
+    #[structopt(flatten)]
+    patch: Patch,
+    /// The ID of the system to test, 1 for the air conditioner and 5 for the thermal radiators.
+    system_id: i64,
+}
+
+/// Runs the diagnostic program, returns each output with the address of the instruction that
+/// produced it.
+fn diagnose(opcodes: &[i64], system_id: i64) -> Result<Vec<(usize, i64)>, failure::Error> {
+    let mut computer = Computer::new(opcodes);
+    let mut input = once(Ok(system_id));
+    let mut outputs = Vec::new();
+
+    loop {
+        let address = computer.ip();
+
+        match computer.step(&mut input)? {
+            Step::Nothing => (),
+            Step::Output(output) => outputs.push((address, output)),
+            Step::NeedsInput => return Err(failure::err_msg("program needs more input")),
+            Step::Terminated => return Ok(outputs),
+        }
+    }
+}
+
+/// The outcome of a diagnostic run.
+#[derive(Debug, PartialEq)]
+enum Diagnosis {
+    /// Every test passed, and the program reported this diagnostic code.
+    Passed(i64),
+    /// Some tests output something other than 0, listed with the address of the instruction that
+    /// output it, out of this many tests.
+    Failed {
+        failed: Vec<(usize, i64)>,
+        tests: usize,
+    },
+}
+
+/// Checks the outputs of a diagnostic run: every test reports 0 if it passed, then the program
+/// reports the diagnostic code.
+fn verify(outputs: &[(usize, i64)]) -> Result<Diagnosis, failure::Error> {
+    let (code, tests) = outputs
+        .split_last()
+        .ok_or_else(|| failure::err_msg("no output!"))?;
+
+    let failed: Vec<_> = tests
+        .iter()
+        .copied()
+        .filter(|(_, output)| *output != 0)
+        .collect();
+
+    if failed.is_empty() {
+        Ok(Diagnosis::Passed(code.1))
+    } else {
+        Ok(Diagnosis::Failed {
+            failed,
+            tests: tests.len(),
+        })
+    }
 }
 
 pub fn run(options: &Options) -> Result<(), failure::Error> {
-    let stdin = stdin();
-    let mut lines = stdin.lock().lines().map(|line| Ok(line?.parse::<i64>()?));
-    Computer::new(&Computer::parse(&options.program)?).run(&mut lines)?;
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let outputs = diagnose(
+        &options.patch.apply(Computer::parse(&input)?)?,
+        options.system_id,
+    )?;
+
+    match verify(&outputs)? {
+        Diagnosis::Passed(code) => println!("{}", code),
+        Diagnosis::Failed { failed, tests } => {
+            return Err(failure::err_msg(format!(
+                "{} of {} tests failed: {}",
+                failed.len(),
+                tests,
+                failed
+                    .iter()
+                    .map(|(address, output)| format!("test at {} output {}", address, output))
+                    .join(", ")
+            )))
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::intcode::Dialect;
+
+    #[test]
+    fn failing_tests_are_reported_with_their_address() {
+        let source = "
+                  in [id]
+                  out #0
+            fail: out [id]
+                  out #0
+                  out #42
+                  hlt
+            id:   data 0
+        ";
+        let program = assemble(source, &Dialect::DAY_5).unwrap();
+
+        assert_eq!(
+            verify(&diagnose(&program, 0).unwrap()).unwrap(),
+            Diagnosis::Passed(42)
+        );
+
+        // the failing test is the OUT at `fail`, after the two-cell IN and OUT before it
+        assert_eq!(
+            verify(&diagnose(&program, 5).unwrap()).unwrap(),
+            Diagnosis::Failed {
+                failed: vec![(4, 5)],
+                tests: 3,
+            }
+        );
+
+        assert!(verify(&[]).is_err());
+    }
+}
@@ -71,7 +71,7 @@ pub fn run(options: &Options) -> Result<(), failure::Error> {
         Options::A => {
             let mut map: HashMap<&str, HashSet<&str>> = HashMap::new();
             for (outer, inner) in orbits {
-                map.entry(outer).or_insert_with(HashSet::new).insert(inner);
+                map.entry(outer).or_default().insert(inner);
             }
             println!("{}", count(&map, "COM", 0));
         }
@@ -1,75 +1,86 @@
-use crate::intcode::{Computer, Step};
+use crate::intcode::{Computer, Engine, Error, Limits};
+use crate::network::{Chain, Network, Ring, Topology};
+use crate::patch::Patch;
 use itertools::iproduct;
 use std::collections::HashSet;
 use std::io::{stdin, Read};
-use std::iter::{once, repeat, repeat_with};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-pub enum Options {
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    part: Part,
+}
+
+#[derive(StructOpt)]
+pub enum Part {
     A,
     B,
 }
 
-fn unique(settings: &Vec<i64>) -> bool {
+fn unique(settings: &[i64]) -> bool {
     settings.iter().collect::<HashSet<&i64>>().len() == settings.len()
 }
 
-fn compute(opcodes: &Vec<i64>, settings: &Vec<i64>) -> Result<i64, failure::Error> {
-    let mut storage: Vec<_> = repeat(0).take(settings.len()).collect();
-    let mut terminated: Vec<_> = repeat(false).take(settings.len()).collect();
-    let mut initial: Vec<_> = settings.iter().copied().map(once).collect();
-
-    let mut computers: Vec<_> = repeat_with(|| Computer::new(&opcodes))
-        .take(settings.len())
-        .collect();
+/// Phase settings that make an amplifier run for longer than this are skipped.
+const LIMITS: Limits = Limits {
+    steps: Some(1_000_000),
+    memory: Some(1 << 20),
+    detect_loops: true,
+};
 
-    loop {
-        for i in 0..settings.len() {
-            let mut input = initial.get_mut(i).unwrap().chain(once(storage[i])).map(Ok);
+fn compute<T: Topology>(
+    opcodes: &[i64],
+    settings: &[i64],
+    topology: T,
+) -> Result<i64, failure::Error> {
+    let mut network = Network::new(opcodes, settings.len(), topology);
+    network.set_limits(LIMITS);
+    network.set_engine(Engine::Decoded);
 
-            loop {
-                match computers[i].step(&mut input)? {
-                    Step::Nothing => (),
-                    Step::Output(o) => {
-                        storage[(i + 1) % 5] = o;
-                        break;
-                    }
-                    Step::Terminated => {
-                        terminated[i] = true;
-                        break;
-                    }
-                }
-            }
-        }
-
-        if terminated.iter().all(|b| *b) {
-            break;
-        }
+    for (i, setting) in settings.iter().enumerate() {
+        network.input(i, *setting);
     }
+    network.input(0, 0);
 
-    Ok(storage[0])
+    Ok(network.run()?.value)
 }
 
 pub fn run(options: &Options) -> Result<(), failure::Error> {
     let mut input = String::new();
     stdin().read_to_string(&mut input)?;
 
-    let opcodes = Computer::parse(&input)?;
+    let opcodes = options.patch.apply(Computer::parse(&input)?)?;
 
-    let values = match options {
-        Options::A => iproduct!(0..5, 0..5, 0..5, 0..5, 0..5),
-        Options::B => iproduct!(5..10, 5..10, 5..10, 5..10, 5..10),
+    let values = match &options.part {
+        Part::A => iproduct!(0..5, 0..5, 0..5, 0..5, 0..5),
+        Part::B => iproduct!(5..10, 5..10, 5..10, 5..10, 5..10),
     };
 
     let mut max = None;
 
     for next in values
         .map(|settings| vec![settings.0, settings.1, settings.2, settings.3, settings.4])
-        .filter(unique)
-        .map(|settings| compute(&opcodes, &settings))
+        .filter(|settings| unique(settings))
+        .map(|settings| match &options.part {
+            Part::A => compute(&opcodes, &settings, Chain::default()),
+            Part::B => compute(&opcodes, &settings, Ring::default()),
+        })
     {
-        let next = next?;
+        let next = match next {
+            Ok(next) => next,
+            Err(err)
+                if err
+                    .downcast_ref::<Error>()
+                    .map(|err| err.kind.is_limit())
+                    .unwrap_or(false) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
         max = Some(
             max.map(|current: i64| std::cmp::max(current, next))
                 .unwrap_or(next),
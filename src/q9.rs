@@ -1,10 +1,20 @@
-use crate::intcode::Computer;
+use crate::intcode::{Computer, Engine};
+use crate::patch::Patch;
+use itertools::Itertools;
 use std::io::{stdin, Read};
 use std::iter::once;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-pub enum Options {
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    part: Part,
+}
+
+#[derive(StructOpt)]
+pub enum Part {
     A,
     B,
 }
@@ -13,13 +23,32 @@ pub fn run(options: &Options) -> Result<(), failure::Error> {
     let mut program = String::new();
     stdin().read_to_string(&mut program)?;
 
-    let input = match options {
-        Options::A => 1,
-        Options::B => 2,
-    };
+    let mut computer = Computer::new(&options.patch.apply(Computer::parse(&program)?)?);
+    computer.set_engine(Engine::Compiled);
 
-    let mut computer = Computer::new(&Computer::parse(&program)?);
-    computer.run(&mut once(Ok(input)))?;
+    match &options.part {
+        Part::A => {
+            // any malfunctioning opcodes are reported before the keycode
+            let outputs = computer.run_collect(&mut once(Ok(1)))?;
+            match outputs.split_last() {
+                Some((keycode, [])) => println!("{}", keycode),
+                Some((_, malfunctions)) => {
+                    return Err(failure::err_msg(format!(
+                        "malfunctioning opcodes: {}",
+                        malfunctions.iter().join(", ")
+                    )))
+                }
+                None => return Err(failure::err_msg("no output!")),
+            }
+        }
+        Part::B => {
+            let outputs = computer.run_take(&mut once(Ok(2)), 1)?;
+            let coordinates = outputs
+                .first()
+                .ok_or_else(|| failure::err_msg("no output!"))?;
+            println!("{}", coordinates);
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,555 @@
+use crate::intcode::{Computer, Dialect, Engine, Error, ErrorKind, Limits};
+use crate::patch::Patch;
+use itertools::Itertools;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::io::{stdin, Read};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    /// A memory cell to vary, as ADDR=LOW..=HIGH.
+    #[structopt(long, number_of_values = 1)]
+    cell: Vec<CellRange>,
+    /// The range of each input in turn, as LOW..=HIGH.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_range))]
+    input: Vec<RangeInclusive<i64>>,
+    /// Matches candidates that leave this value at address 0.
+    #[structopt(long, required_unless = "output")]
+    cell0: Option<i64>,
+    /// Matches candidates whose last output is this value.
+    #[structopt(long)]
+    output: Option<i64>,
+    /// Reports every match instead of the first.
+    #[structopt(long)]
+    all: bool,
+    /// The number of threads to run candidates on, all available cores by default.
+    #[structopt(long)]
+    threads: Option<usize>,
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+pub struct CellRange {
+    address: usize,
+    range: RangeInclusive<i64>,
+}
+
+/// Where the value of a parameter goes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Cell(usize),
+    /// The `n`th value read by an input instruction.
+    Input(usize),
+}
+
+/// A slot and the values to try in it.
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub slot: Slot,
+    pub range: RangeInclusive<i64>,
+}
+
+/// What a candidate left behind when it terminated.
+pub struct Run {
+    /// The value at address 0.
+    pub cell0: i64,
+    pub outputs: Vec<i64>,
+}
+
+/// Tries every combination of parameter values, in order with the last parameter varying
+/// fastest. Candidates that fail or exceed the limits do not match.
+pub struct Search<'a> {
+    opcodes: &'a [i64],
+    dialect: &'static Dialect,
+    engine: Engine,
+    limits: Limits,
+    parameters: Vec<Parameter>,
+    /// The number of values of each parameter.
+    lengths: Vec<usize>,
+    /// The number of candidates, the product of `lengths`.
+    len: usize,
+    /// The number of parameters that are inputs.
+    inputs: usize,
+    threads: usize,
+}
+
+// Parsing
+
+fn parse_range(text: &str) -> Result<RangeInclusive<i64>, failure::Error> {
+    let mut split = text.splitn(2, "..=");
+    match (split.next(), split.next()) {
+        (Some(low), Some(high)) => Ok(low.trim().parse()?..=high.trim().parse()?),
+        (Some(value), None) => {
+            let value = value.trim().parse()?;
+            Ok(value..=value)
+        }
+        _ => unreachable!(),
+    }
+}
+
+impl FromStr for CellRange {
+    type Err = failure::Error;
+
+    fn from_str(text: &str) -> Result<CellRange, failure::Error> {
+        let mut split = text.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(address), Some(range)) => Ok(CellRange {
+                address: address.trim().parse()?,
+                range: parse_range(range)?,
+            }),
+            _ => Err(failure::err_msg(format!(
+                "expected ADDR=LOW..=HIGH, found `{}`",
+                text
+            ))),
+        }
+    }
+}
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Slot::Cell(address) => write!(f, "[{}]", address),
+            Slot::Input(n) => write!(f, "in{}", n),
+        }
+    }
+}
+
+// Searching
+
+/// Whether an error means the candidate does not match, rather than that the search is broken.
+fn rejects(err: &Error) -> bool {
+    err.kind.is_limit()
+        || matches!(
+            err.kind,
+            ErrorKind::InvalidOpcode
+                | ErrorKind::InvalidMode { .. }
+                | ErrorKind::InvalidDestMode { .. }
+                | ErrorKind::NegativeAddress(_)
+                | ErrorKind::JumpOutOfRange(_)
+                | ErrorKind::Overflow
+        )
+}
+
+impl<'a> Search<'a> {
+    pub fn new(
+        opcodes: &'a [i64],
+        dialect: &'static Dialect,
+        parameters: Vec<Parameter>,
+    ) -> Result<Search<'a>, failure::Error> {
+        let mut inputs: Vec<_> = parameters
+            .iter()
+            .filter_map(|parameter| match parameter.slot {
+                Slot::Input(n) => Some(n),
+                Slot::Cell(_) => None,
+            })
+            .collect();
+        inputs.sort_unstable();
+        if inputs.iter().copied().ne(0..inputs.len()) {
+            return Err(failure::err_msg(
+                "inputs must be numbered from 0 without gaps",
+            ));
+        }
+
+        if let Some(parameter) = parameters.iter().find(|parameter| match parameter.slot {
+            Slot::Cell(address) => address >= opcodes.len(),
+            Slot::Input(_) => false,
+        }) {
+            return Err(failure::err_msg(format!(
+                "{} is outside the program of {} cells",
+                parameter.slot,
+                opcodes.len()
+            )));
+        }
+
+        let lengths = parameters
+            .iter()
+            .map(|parameter| {
+                let (low, high) = (*parameter.range.start(), *parameter.range.end());
+                let len = (i128::from(high) - i128::from(low) + 1).max(0);
+                usize::try_from(len).map_err(|_| {
+                    failure::err_msg(format!("{} has too many values to search", parameter.slot))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let len = lengths
+            .iter()
+            .try_fold(1usize, |len, values| len.checked_mul(*values))
+            .ok_or_else(|| failure::err_msg("too many combinations of parameters to search"))?;
+
+        Ok(Search {
+            opcodes,
+            dialect,
+            engine: Engine::Decoded,
+            limits: Limits::default(),
+            parameters,
+            lengths,
+            len,
+            inputs: inputs.len(),
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        })
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// The number of candidates.
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The parameter values of candidate `index`.
+    fn candidate(&self, mut index: usize) -> Vec<i64> {
+        let mut values: Vec<_> = self
+            .parameters
+            .iter()
+            .zip(self.lengths.iter())
+            .rev()
+            .map(|(parameter, len)| {
+                let value = parameter.range.start() + (index % len) as i64;
+                index /= len;
+                value
+            })
+            .collect();
+        values.reverse();
+        values
+    }
+
+    fn execute(&self, computer: &Computer, values: &[i64]) -> Result<Option<Run>, Error> {
+        let mut overrides = Vec::new();
+        let mut inputs = vec![0; self.inputs];
+
+        for (parameter, value) in self.parameters.iter().zip(values.iter()) {
+            match parameter.slot {
+                Slot::Cell(address) => overrides.push((address, *value)),
+                Slot::Input(n) => inputs[n] = *value,
+            }
+        }
+
+        let mut outputs = Vec::new();
+        let result =
+            computer
+                .fork(&overrides)
+                .run_sink(&mut inputs.into_iter().map(Ok), |output| {
+                    outputs.push(output);
+                    Ok(())
+                });
+
+        match result {
+            Ok(cell0) => Ok(Some(Run { cell0, outputs })),
+            Err(ref err) if rejects(err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Runs candidates on every thread, returning matches and errors in order. Candidates
+    /// after the first one that sets `stop` are skipped.
+    fn run<P>(&self, matches: P, stop_on_match: bool) -> Vec<(usize, Result<Vec<i64>, Error>)>
+    where
+        P: Fn(&Run) -> bool + Sync,
+    {
+        let mut computer = Computer::new_dialect(self.opcodes, self.dialect);
+        computer.set_limits(self.limits);
+        computer.set_engine(self.engine);
+
+        let len = self.len();
+        let stop = AtomicUsize::new(len);
+
+        let mut found: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|worker| {
+                    let (computer, matches, stop) = (&computer, &matches, &stop);
+
+                    scope.spawn(move || {
+                        let mut found = Vec::new();
+
+                        for index in (worker..len).step_by(self.threads) {
+                            if index > stop.load(Ordering::Relaxed) {
+                                break;
+                            }
+
+                            let values = self.candidate(index);
+                            match self.execute(computer, &values) {
+                                Ok(Some(run)) if matches(&run) => {
+                                    if stop_on_match {
+                                        stop.fetch_min(index, Ordering::Relaxed);
+                                    }
+                                    found.push((index, Ok(values)));
+                                }
+                                Ok(_) => (),
+                                Err(err) => {
+                                    stop.fetch_min(index, Ordering::Relaxed);
+                                    found.push((index, Err(err)));
+                                }
+                            }
+                        }
+
+                        found
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        found.sort_by_key(|(index, _)| *index);
+        found
+    }
+
+    /// The first candidate in order that matches, stopping the other threads once it is found.
+    pub fn first<P>(&self, matches: P) -> Result<Option<Vec<i64>>, Error>
+    where
+        P: Fn(&Run) -> bool + Sync,
+    {
+        self.run(matches, true)
+            .into_iter()
+            .next()
+            .map(|(_, result)| result)
+            .transpose()
+    }
+
+    /// Every candidate that matches, in order.
+    pub fn all<P>(&self, matches: P) -> Result<Vec<Vec<i64>>, Error>
+    where
+        P: Fn(&Run) -> bool + Sync,
+    {
+        self.run(matches, false)
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect()
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+}
+
+// Main
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let opcodes = options.patch.apply(Computer::parse(&input)?)?;
+
+    let parameters = options
+        .cell
+        .iter()
+        .map(|cell| Parameter {
+            slot: Slot::Cell(cell.address),
+            range: cell.range.clone(),
+        })
+        .chain(
+            options
+                .input
+                .iter()
+                .enumerate()
+                .map(|(n, range)| Parameter {
+                    slot: Slot::Input(n),
+                    range: range.clone(),
+                }),
+        )
+        .collect();
+
+    let mut search = Search::new(&opcodes, options.dialect, parameters)?;
+    search.set_limits(Limits {
+        steps: Some(1_000_000),
+        memory: Some(1 << 20),
+        detect_loops: true,
+    });
+    if let Some(threads) = options.threads {
+        search.set_threads(threads);
+    }
+
+    let matches = |run: &Run| {
+        options.cell0.map_or(true, |value| run.cell0 == value)
+            && options
+                .output
+                .map_or(true, |value| run.outputs.last() == Some(&value))
+    };
+
+    let found = if options.all {
+        search.all(matches)?
+    } else {
+        search.first(matches)?.into_iter().collect()
+    };
+
+    for values in found.iter() {
+        println!(
+            "{}",
+            search
+                .parameters()
+                .iter()
+                .zip(values.iter())
+                .map(|(parameter, value)| format!("{}={}", parameter.slot, value))
+                .join(" ")
+        );
+    }
+
+    if found.is_empty() {
+        return Err(failure::err_msg("no match!"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Outputs in0 + 10 * [13], and leaves it at address 0.
+    const PROGRAM: [i64; 14] = [3, 0, 1002, 13, 10, 13, 1, 0, 13, 0, 4, 0, 99, 0];
+
+    fn digits() -> Search<'static> {
+        Search::new(
+            &PROGRAM,
+            &Dialect::FULL,
+            vec![
+                Parameter {
+                    slot: Slot::Cell(13),
+                    range: 0..=9,
+                },
+                Parameter {
+                    slot: Slot::Input(0),
+                    range: 0..=9,
+                },
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn candidates_are_in_order() {
+        let search = Search::new(
+            &[99],
+            &Dialect::FULL,
+            vec![
+                Parameter {
+                    slot: Slot::Input(0),
+                    range: 1..=2,
+                },
+                Parameter {
+                    slot: Slot::Input(1),
+                    range: -1..=1,
+                },
+            ],
+        )
+        .unwrap();
+
+        let candidates: Vec<_> = (0..search.len()).map(|i| search.candidate(i)).collect();
+        assert_eq!(
+            candidates,
+            [[1, -1], [1, 0], [1, 1], [2, -1], [2, 0], [2, 1]]
+        );
+    }
+
+    #[test]
+    fn search_space_size() {
+        let search = |ranges: Vec<RangeInclusive<i64>>| {
+            let parameters = ranges
+                .into_iter()
+                .enumerate()
+                .map(|(n, range)| Parameter {
+                    slot: Slot::Input(n),
+                    range,
+                })
+                .collect();
+            Search::new(&[99], &Dialect::FULL, parameters)
+        };
+
+        assert_eq!(search(vec![0..=99, -5..=4]).unwrap().len(), 1000);
+        assert_eq!(
+            search(vec![0..=99, RangeInclusive::new(5, 4)])
+                .unwrap()
+                .len(),
+            0
+        );
+        assert_eq!(
+            search(vec![i64::MIN..=i64::MAX]).err().unwrap().to_string(),
+            "in0 has too many values to search"
+        );
+        assert_eq!(
+            search(vec![0..=i64::MAX, 0..=1]).err().unwrap().to_string(),
+            "too many combinations of parameters to search"
+        );
+    }
+
+    #[test]
+    fn first_match_in_order() {
+        let mut search = digits();
+        search.set_threads(4);
+
+        assert_eq!(
+            search.first(|run| run.cell0 == 42).unwrap(),
+            Some(vec![4, 2])
+        );
+        assert_eq!(
+            search.first(|run| run.outputs == [57]).unwrap(),
+            Some(vec![5, 7])
+        );
+        assert_eq!(
+            search.first(|run| run.outputs[0] >= 50).unwrap(),
+            Some(vec![5, 0])
+        );
+        assert_eq!(search.first(|run| run.cell0 == 100).unwrap(), None);
+    }
+
+    #[test]
+    fn all_matches_include_the_end_of_ranges() {
+        let mut search = digits();
+        search.set_threads(3);
+
+        let found = search.all(|run| run.cell0 % 11 == 0).unwrap();
+        assert_eq!(found, (0..=9).map(|i| vec![i, i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn failing_candidates_do_not_match() {
+        // reads the cell that [1] points at, which fails if it is negative
+        let cell = |program| {
+            Search::new(
+                program,
+                &Dialect::FULL,
+                vec![Parameter {
+                    slot: Slot::Cell(1),
+                    range: -2..=2,
+                }],
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            cell(&[1, 0, 0, 0, 99]).all(|_| true).unwrap(),
+            [[0], [1], [2]]
+        );
+
+        // needing input the search does not provide is an error, not a mismatch
+        assert_eq!(
+            cell(&[3, 0, 3, 0, 99])
+                .first(|_| true)
+                .unwrap_err()
+                .to_string(),
+            "no input at 0 (3)"
+        );
+    }
+}
@@ -0,0 +1,556 @@
+use crate::intcode::{Computer, Dialect, Handler, Instruction, Mode, Opcode};
+use crate::patch::Patch;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{stdin, Read};
+use std::rc::Rc;
+use structopt::StructOpt;
+
+// Types
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    /// A memory cell to treat as a symbol, such as 1 and 2 for day 2's noun and verb.
+    #[structopt(short, long)]
+    cell: Vec<usize>,
+    #[structopt(long, default_value = "full", parse(try_from_str = Dialect::named))]
+    dialect: &'static Dialect,
+}
+
+/// An unknown value that expressions are built from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Symbol {
+    /// The initial value of a memory cell.
+    Cell(usize),
+    /// The value read by the `n`th input instruction.
+    Input(usize),
+}
+
+/// The value of a memory cell or output, in terms of symbols.
+#[derive(Debug)]
+pub enum Expr {
+    Const(i64),
+    Symbol(Symbol),
+    /// A value read from an address that depends on symbols.
+    Unknown,
+    /// An instruction that stores a function of two arguments, such as `add`.
+    Binary(&'static Instruction, Rc<Expr>, Rc<Expr>),
+}
+
+/// An expression of the form `constant + coefficient * symbol + ...`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Linear {
+    pub constant: i64,
+    pub terms: BTreeMap<Symbol, i64>,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub ip: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidOpcode,
+    InvalidMode {
+        argi: usize,
+        mode: i64,
+    },
+    InvalidDestMode {
+        argi: usize,
+        mode: i64,
+    },
+    /// An opcode or the argument of a jump or `arb` depends on symbols, so the path through the
+    /// program is not known.
+    SymbolicControl,
+    /// A write to an address that depends on symbols.
+    SymbolicWrite,
+    NegativeAddress(i64),
+    Overflow,
+    StepLimitExceeded(u64),
+    MemoryLimitExceeded(usize),
+}
+
+/// Executes a program with values that may depend on symbols. Execution follows a single path,
+/// so it fails if a jump depends on symbols.
+pub struct Machine {
+    memory: Vec<Rc<Expr>>,
+    i: usize,
+    relative_base: i64,
+    dialect: &'static Dialect,
+    steps: u64,
+    inputs: usize,
+    outputs: Vec<Rc<Expr>>,
+}
+
+const STEP_LIMIT: u64 = 1_000_000;
+const MEMORY_LIMIT: usize = 1 << 16;
+
+// Formatting
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symbol::Cell(address) => write!(f, "[{}]", address),
+            Symbol::Input(n) => write!(f, "in{}", n),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Symbol(symbol) => write!(f, "{}", symbol),
+            Expr::Unknown => write!(f, "?"),
+            Expr::Binary(instruction, a, b) => {
+                let operator = match instruction.handler {
+                    Handler::Builtin(Opcode::Add) => "+",
+                    Handler::Builtin(Opcode::Mul) => "*",
+                    Handler::Builtin(Opcode::Lt) => "<",
+                    Handler::Builtin(Opcode::Eq) => "==",
+                    _ => return write!(f, "{}({}, {})", instruction.mnemonic, a, b),
+                };
+                write!(f, "({} {} {})", a, operator, b)
+            }
+        }
+    }
+}
+
+impl Display for Linear {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (symbol, coefficient) in self.terms.iter() {
+            match coefficient {
+                1 => write!(f, "{} + ", symbol)?,
+                _ => write!(f, "{} * {} + ", coefficient, symbol)?,
+            }
+        }
+        write!(f, "{}", self.constant)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.kind, self.ip)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+            ErrorKind::InvalidMode { argi, mode } => {
+                write!(f, "invalid mode {} for argument {}", mode, argi)
+            }
+            ErrorKind::InvalidDestMode { argi, mode } => {
+                write!(f, "invalid dest mode {} for argument {}", mode, argi)
+            }
+            ErrorKind::SymbolicControl => write!(f, "control flow depends on symbols"),
+            ErrorKind::SymbolicWrite => write!(f, "write to an address that depends on symbols"),
+            ErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::StepLimitExceeded(limit) => write!(f, "exceeded step limit of {}", limit),
+            ErrorKind::MemoryLimitExceeded(limit) => {
+                write!(f, "exceeded memory limit of {}", limit)
+            }
+        }
+    }
+}
+
+impl failure::Fail for Error {}
+
+// Expressions
+
+impl Expr {
+    /// The value of the expression if it does not depend on symbols.
+    pub fn value(&self) -> Option<i64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Applies a binary instruction, folding constants.
+    fn binary(
+        instruction: &'static Instruction,
+        a: Rc<Expr>,
+        b: Rc<Expr>,
+    ) -> Result<Rc<Expr>, ErrorKind> {
+        let function: fn(i64, i64) -> Option<i64> = match instruction.handler {
+            Handler::Builtin(Opcode::Add) => i64::checked_add,
+            Handler::Builtin(Opcode::Mul) => i64::checked_mul,
+            Handler::Builtin(Opcode::Lt) => |a, b| Some((a < b) as i64),
+            Handler::Builtin(Opcode::Eq) => |a, b| Some((a == b) as i64),
+            Handler::Operator(function) => function,
+            Handler::Builtin(_) => unreachable!("not a binary instruction"),
+        };
+
+        let expr = match (a.value(), b.value()) {
+            (Some(a), Some(b)) => Expr::Const(function(a, b).ok_or(ErrorKind::Overflow)?),
+            (Some(0), _) if instruction.is(Opcode::Add) => return Ok(b),
+            (_, Some(0)) if instruction.is(Opcode::Add) => return Ok(a),
+            (Some(1), _) if instruction.is(Opcode::Mul) => return Ok(b),
+            (_, Some(1)) if instruction.is(Opcode::Mul) => return Ok(a),
+            _ if matches!(*a, Expr::Unknown) || matches!(*b, Expr::Unknown) => Expr::Unknown,
+            _ => Expr::Binary(instruction, a, b),
+        };
+
+        Ok(Rc::new(expr))
+    }
+
+    /// The expression as a linear combination of symbols, if it is one.
+    pub fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Const(value) => Some(Linear {
+                constant: *value,
+                terms: BTreeMap::new(),
+            }),
+            Expr::Symbol(symbol) => Some(Linear {
+                constant: 0,
+                terms: Some((*symbol, 1)).into_iter().collect(),
+            }),
+            Expr::Binary(instruction, a, b) if instruction.is(Opcode::Add) => {
+                a.linear()?.add(&b.linear()?)
+            }
+            Expr::Binary(instruction, a, b) if instruction.is(Opcode::Mul) => {
+                match (a.linear()?, b.linear()?) {
+                    (a, b) if a.terms.is_empty() => b.scale(a.constant),
+                    (a, b) if b.terms.is_empty() => a.scale(b.constant),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Linear {
+    fn add(mut self, other: &Linear) -> Option<Linear> {
+        self.constant = self.constant.checked_add(other.constant)?;
+        for (symbol, coefficient) in other.terms.iter() {
+            let term = self.terms.entry(*symbol).or_insert(0);
+            *term = term.checked_add(*coefficient)?;
+        }
+        self.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(self)
+    }
+
+    fn scale(mut self, factor: i64) -> Option<Linear> {
+        self.constant = self.constant.checked_mul(factor)?;
+        for coefficient in self.terms.values_mut() {
+            *coefficient = coefficient.checked_mul(factor)?;
+        }
+        self.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(self)
+    }
+
+    /// The coefficient of a symbol, 0 if the expression does not depend on it.
+    pub fn coefficient(&self, symbol: Symbol) -> i64 {
+        self.terms.get(&symbol).copied().unwrap_or(0)
+    }
+}
+
+// Execution
+
+impl Machine {
+    /// Loads a program, treating the cells at `symbols`, which must all be within it, as unknown.
+    pub fn new(
+        opcodes: &[i64],
+        dialect: &'static Dialect,
+        symbols: &[usize],
+    ) -> Result<Machine, failure::Error> {
+        let mut memory: Vec<_> = opcodes
+            .iter()
+            .map(|opcode| Rc::new(Expr::Const(*opcode)))
+            .collect();
+
+        for address in symbols.iter().copied() {
+            match memory.get_mut(address) {
+                Some(cell) => *cell = Rc::new(Expr::Symbol(Symbol::Cell(address))),
+                None => {
+                    return Err(failure::err_msg(format!(
+                        "{} is outside the program of {} cells",
+                        Symbol::Cell(address),
+                        opcodes.len()
+                    )))
+                }
+            }
+        }
+
+        Ok(Machine {
+            memory,
+            i: 0,
+            relative_base: 0,
+            dialect,
+            steps: 0,
+            inputs: 0,
+            outputs: Vec::new(),
+        })
+    }
+
+    pub fn read(&self, address: usize) -> Rc<Expr> {
+        self.memory
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Expr::Const(0)))
+    }
+
+    pub fn outputs(&self) -> &[Rc<Expr>] {
+        &self.outputs
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { ip: self.i, kind }
+    }
+
+    /// The value of a cell that must not depend on symbols.
+    fn concrete(&self, address: usize) -> Result<i64, Error> {
+        self.read(address)
+            .value()
+            .ok_or_else(|| self.error(ErrorKind::SymbolicControl))
+    }
+
+    fn mode(&self, opcode: i64, argi: usize) -> Result<Mode, Error> {
+        self.dialect
+            .mode(opcode, argi)
+            .map_err(|mode| self.error(ErrorKind::InvalidMode { argi, mode }))
+    }
+
+    /// The address an argument refers to, `None` if it depends on symbols.
+    fn address(&self, opcode: i64, argi: usize) -> Result<Option<usize>, Error> {
+        let mode = self.mode(opcode, argi)?;
+        let value = self.read(self.i + argi).value();
+
+        let address = match (mode, value) {
+            (Mode::Immediate, _) => return Ok(Some(self.i + argi)),
+            (_, None) => return Ok(None),
+            (Mode::Position, Some(value)) => value,
+            (Mode::Relative, Some(value)) => self
+                .relative_base
+                .checked_add(value)
+                .ok_or_else(|| self.error(ErrorKind::Overflow))?,
+        };
+
+        if address < 0 {
+            Err(self.error(ErrorKind::NegativeAddress(address)))
+        } else {
+            Ok(Some(address as usize))
+        }
+    }
+
+    fn load(&self, opcode: i64, argi: usize) -> Result<Rc<Expr>, Error> {
+        Ok(match self.address(opcode, argi)? {
+            Some(address) => self.read(address),
+            None => Rc::new(Expr::Unknown),
+        })
+    }
+
+    fn store(&mut self, opcode: i64, argi: usize, value: Rc<Expr>) -> Result<(), Error> {
+        if let Mode::Immediate = self.mode(opcode, argi)? {
+            return Err(self.error(ErrorKind::InvalidDestMode {
+                argi,
+                mode: Mode::Immediate.digit(),
+            }));
+        }
+
+        let address = self
+            .address(opcode, argi)?
+            .ok_or_else(|| self.error(ErrorKind::SymbolicWrite))?;
+
+        if address >= self.memory.len() {
+            if address >= MEMORY_LIMIT {
+                return Err(self.error(ErrorKind::MemoryLimitExceeded(MEMORY_LIMIT)));
+            }
+            self.memory.resize(address + 1, Rc::new(Expr::Const(0)));
+        }
+        self.memory[address] = value;
+
+        Ok(())
+    }
+
+    /// Runs the program until it halts.
+    pub fn run(&mut self) -> Result<(), Error> {
+        loop {
+            if self.steps >= STEP_LIMIT {
+                return Err(self.error(ErrorKind::StepLimitExceeded(STEP_LIMIT)));
+            }
+            self.steps += 1;
+
+            let opcode = self.concrete(self.i)?;
+            let instruction = self
+                .dialect
+                .decode(opcode)
+                .ok_or_else(|| self.error(ErrorKind::InvalidOpcode))?;
+            let next = self.i + instruction.arity + 1;
+
+            match instruction.handler {
+                Handler::Builtin(Opcode::In) => {
+                    let value = Rc::new(Expr::Symbol(Symbol::Input(self.inputs)));
+                    self.inputs += 1;
+                    self.store(opcode, 1, value)?;
+                }
+                Handler::Builtin(Opcode::Out) => {
+                    let value = self.load(opcode, 1)?;
+                    self.outputs.push(value);
+                }
+                Handler::Builtin(Opcode::Jnz) | Handler::Builtin(Opcode::Jz) => {
+                    let condition = self.load(opcode, 1)?.value();
+                    let condition =
+                        condition.ok_or_else(|| self.error(ErrorKind::SymbolicControl))?;
+
+                    if (condition != 0) == instruction.is(Opcode::Jnz) {
+                        let target = self.load(opcode, 2)?.value();
+                        let target =
+                            target.ok_or_else(|| self.error(ErrorKind::SymbolicControl))?;
+                        if target < 0 {
+                            return Err(self.error(ErrorKind::NegativeAddress(target)));
+                        }
+                        self.i = target as usize;
+                        continue;
+                    }
+                }
+                Handler::Builtin(Opcode::Arb) => {
+                    let offset = self.load(opcode, 1)?.value();
+                    let offset = offset.ok_or_else(|| self.error(ErrorKind::SymbolicControl))?;
+                    self.relative_base = self
+                        .relative_base
+                        .checked_add(offset)
+                        .ok_or_else(|| self.error(ErrorKind::Overflow))?;
+                }
+                Handler::Builtin(Opcode::Hlt) => return Ok(()),
+                _ => {
+                    let (a, b) = (self.load(opcode, 1)?, self.load(opcode, 2)?);
+                    let value = Expr::binary(instruction, a, b).map_err(|kind| self.error(kind))?;
+                    self.store(opcode, 3, value)?;
+                }
+            }
+
+            self.i = next;
+        }
+    }
+}
+
+// Main
+
+fn describe(expr: &Expr) -> String {
+    match expr.linear() {
+        Some(linear) => linear.to_string(),
+        None => expr.to_string(),
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input)?;
+
+    let mut machine = Machine::new(
+        &options.patch.apply(Computer::parse(&input)?)?,
+        options.dialect,
+        &options.cell,
+    )?;
+    machine.run()?;
+
+    for (i, output) in machine.outputs().iter().enumerate() {
+        println!("output {} = {}", i, describe(output));
+    }
+    println!("[0] = {}", describe(&machine.read(0)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear(constant: i64, terms: &[(Symbol, i64)]) -> Option<Linear> {
+        Some(Linear {
+            constant,
+            terms: terms.iter().copied().collect(),
+        })
+    }
+
+    #[test]
+    fn day_2_is_linear() {
+        let mut machine = Machine::new(
+            &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            &Dialect::DAY_2,
+            &[9, 10],
+        )
+        .unwrap();
+        machine.run().unwrap();
+
+        assert_eq!(
+            machine.read(0).linear(),
+            linear(0, &[(Symbol::Cell(9), 50), (Symbol::Cell(10), 50)])
+        );
+        assert_eq!(machine.read(0).to_string(), "(([9] + [10]) * 50)");
+    }
+
+    #[test]
+    fn reads_through_symbols_are_unknown() {
+        // adds the cells the noun and verb point at, then overwrites the result
+        let mut machine =
+            Machine::new(&[1, 0, 0, 3, 1, 1, 2, 0, 99], &Dialect::DAY_2, &[1, 2]).unwrap();
+        machine.run().unwrap();
+
+        assert!(matches!(*machine.read(3), Expr::Unknown));
+        assert_eq!(machine.read(0).to_string(), "([1] + [2])");
+    }
+
+    #[test]
+    fn outputs_of_inputs() {
+        // outputs 3 * in0 + 1, then in0 < in1
+        let program = [
+            3, 30, 3, 31, 1002, 30, 3, 32, 101, 1, 32, 32, 4, 32, 7, 30, 31, 32, 4, 32, 99,
+        ];
+        let mut machine = Machine::new(&program, &Dialect::FULL, &[]).unwrap();
+        machine.run().unwrap();
+
+        let outputs = machine.outputs();
+        assert_eq!(outputs[0].to_string(), "(1 + (in0 * 3))");
+        assert_eq!(outputs[0].linear(), linear(1, &[(Symbol::Input(0), 3)]));
+        assert_eq!(outputs[1].to_string(), "(in0 < in1)");
+        assert_eq!(outputs[1].linear(), None);
+    }
+
+    #[test]
+    fn branches_on_symbols_fail() {
+        let mut machine = Machine::new(&[3, 5, 1005, 5, 0, 99], &Dialect::FULL, &[]).unwrap();
+
+        assert_eq!(
+            machine.run().unwrap_err().to_string(),
+            "control flow depends on symbols at 2"
+        );
+    }
+
+    #[test]
+    fn symbols_must_be_within_the_program() {
+        assert_eq!(
+            Machine::new(&[99], &Dialect::FULL, &[usize::MAX])
+                .err()
+                .unwrap()
+                .to_string(),
+            format!("[{}] is outside the program of 1 cells", usize::MAX)
+        );
+    }
+
+    #[test]
+    fn invalid_modes_fail() {
+        // `in` to an immediate argument, which must not overwrite the argument itself
+        let mut machine = Machine::new(&[103, 0, 99], &Dialect::FULL, &[]).unwrap();
+        assert_eq!(
+            machine.run().unwrap_err().to_string(),
+            "invalid dest mode 1 for argument 1 at 0"
+        );
+
+        // a relative argument, which day 5 does not support
+        let mut machine = Machine::new(&[204, 0, 99], &Dialect::DAY_5, &[]).unwrap();
+        assert_eq!(
+            machine.run().unwrap_err().to_string(),
+            "invalid mode 2 for argument 1 at 0"
+        );
+    }
+}
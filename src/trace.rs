@@ -0,0 +1,169 @@
+use crate::intcode::trace::{parse, Event};
+use crate::intcode::{Computer, Step};
+use crate::patch::Patch;
+use std::fs::{read_to_string, File};
+use std::io::{stdin, BufWriter, Read, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// Replaying
+
+/// Where a replay first differs from its trace.
+#[derive(Debug, PartialEq, Eq)]
+enum Divergence {
+    /// The event at an index differs from the instruction that was actually executed.
+    Event(usize, Event),
+    /// The program terminated or ran out of input before the event at an index.
+    Stopped(usize, &'static str),
+}
+
+/// Re-executes a program against a trace, feeding it the recorded inputs. Returns where the run
+/// first differs from the trace, if it does.
+fn replay(opcodes: &[i64], events: &[Event]) -> Result<Option<Divergence>, failure::Error> {
+    let mut computer = Computer::new(opcodes);
+    computer.enable_trace();
+
+    let mut inputs = events.iter().filter_map(|event| event.input).map(Ok);
+
+    for (index, expected) in events.iter().enumerate() {
+        let step = computer.step(&mut inputs);
+
+        if let Some(actual) = computer.take_trace().pop() {
+            if actual != *expected {
+                return Ok(Some(Divergence::Event(index, actual)));
+            }
+        }
+
+        let reason = match step? {
+            Step::NeedsInput => "ran out of input",
+            Step::Terminated => "terminated",
+            _ => continue,
+        };
+
+        if index + 1 < events.len() {
+            return Ok(Some(Divergence::Stopped(index + 1, reason)));
+        }
+    }
+
+    Ok(None)
+}
+
+// Main
+
+#[derive(StructOpt)]
+pub struct Options {
+    #[structopt(flatten)]
+    patch: Patch,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    Record {
+        #[structopt(parse(from_os_str))]
+        trace: PathBuf,
+        #[structopt(short, long)]
+        input: Vec<i64>,
+    },
+    Replay {
+        #[structopt(parse(from_os_str))]
+        trace: PathBuf,
+    },
+}
+
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let mut program = String::new();
+    stdin().read_to_string(&mut program)?;
+
+    let opcodes = options.patch.apply(Computer::parse(&program)?)?;
+
+    match &options.command {
+        Command::Record { trace, input } => {
+            let mut computer = Computer::new(&opcodes);
+            computer.enable_trace();
+
+            let result = computer.run_sink(&mut input.iter().copied().map(Ok), |output| {
+                println!("{}", output);
+                Ok(())
+            });
+
+            let mut file = BufWriter::new(File::create(trace)?);
+            for event in computer.take_trace() {
+                writeln!(file, "{}", event)?;
+            }
+
+            result?;
+        }
+        Command::Replay { trace } => {
+            let events = parse(&read_to_string(trace)?)?;
+
+            match replay(&opcodes, &events)? {
+                None => println!("replayed {} events, no divergence", events.len()),
+                Some(Divergence::Event(index, actual)) => {
+                    println!("diverged at event {}", index);
+                    println!("expected: {}", events[index]);
+                    println!("actual:   {}", actual);
+                }
+                Some(Divergence::Stopped(index, reason)) => {
+                    println!("diverged at event {}", index);
+                    println!("expected: {}", events[index]);
+                    println!("actual:   program {}", reason);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(opcodes: &[i64], input: &[i64]) -> Vec<Event> {
+        let mut computer = Computer::new(opcodes);
+        computer.enable_trace();
+        computer.run(&mut input.iter().copied().map(Ok)).unwrap();
+        computer.take_trace()
+    }
+
+    #[test]
+    fn replay_matches_its_own_trace() {
+        let program = [3, 9, 1001, 9, 1, 9, 4, 9, 99, 0];
+        let events = record(&program, &[41]);
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(replay(&program, &events).unwrap(), None);
+    }
+
+    #[test]
+    fn replay_reports_divergence() {
+        let program = [3, 9, 1001, 9, 1, 9, 4, 9, 99, 0];
+        let events = record(&program, &[41]);
+
+        let mut changed = program;
+        changed[4] = 2;
+        match replay(&changed, &events).unwrap() {
+            Some(Divergence::Event(1, actual)) => assert_eq!(actual.writes, [(9, 43)]),
+            divergence => panic!("unexpected divergence {:?}", divergence),
+        }
+
+        // the trace continues after the program terminates
+        let mut longer = events.clone();
+        longer.push(events[0].clone());
+        assert_eq!(
+            replay(&program, &longer).unwrap(),
+            Some(Divergence::Stopped(4, "terminated"))
+        );
+    }
+
+    #[test]
+    fn unresolved_arguments_keep_their_position() {
+        let mut computer = Computer::new(&[1, -1, 0, 0, 99]);
+        computer.enable_trace();
+
+        assert!(computer.run(&mut std::iter::empty()).is_err());
+        assert_eq!(computer.take_trace()[0].args, [None, Some(1), Some(0)]);
+    }
+}